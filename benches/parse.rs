@@ -0,0 +1,94 @@
+//! Compares the speed of `SexprParser` and `FastParser`, run with `cargo bench`. Both readers
+//! first scan the input for forms nested deeper than `max_depth`, with the same scan, which is
+//! timed as part of each of them.
+
+use std::time::{Duration, Instant};
+
+use racket_wellformed::{fast_parser::FastParser, sexpr_parser::SexprParser};
+
+/// Makes a program that looks like the ones written in class, with definitions, conditionals,
+/// comments and a few deeply nested expressions.
+fn corpus(functions: usize) -> String {
+    let mut program = String::from("#lang racket\n");
+    for i in 0..functions {
+        program.push_str(&format!(
+            ";; Number Number -> Number\n\
+             ;; combines the two numbers, see #| the notes |#\n\
+             (define (f{i} x y)\n  \
+               (cond [(> x {i}) (+ x (* y -3))]\n        \
+                     [(string=? \"case {i}\" \"done\") 'done]\n        \
+                     [else (list x y 2.5 #t #:key `(,x . ,y))]))\n\
+             (check-expect (f{i} 1 2) {i}) #;(f{i} 3 4)\n\n"
+        ));
+    }
+    program.push_str(&"(a ".repeat(200));
+    program.push_str(&")".repeat(200));
+    program
+}
+
+/// Makes a program that goes through the rarer parts of the syntax: chars, byte strings, here
+/// strings, regexps, numbers in every radix, vectors, hash tables, prefabs and symbols with
+/// pipes and escapes.
+fn mixed(forms: usize) -> String {
+    let mut program = String::new();
+    for i in 0..forms {
+        program.push_str(&format!(
+            "(define table{i} #hash((#\\a . #\"bytes\\x41\") (#\\space . #rx\"a+b*\")))\n\
+             (list #b101 #o17 #xFF #e1.5 #i1/3 1e10 -inf.0 1+2i 3@1.5 {i}/7 #d#e10)\n\
+             #(1 2 #3(a) #s(point |x y| a\\ b) #&box #'stx #`(a #,b #,@c))\n\
+             #<<EOF\nhere string {i}\nEOF\n\
+             (\"\\u03bb \\n\\t escapes\" #:key #\\u3bb #\\newline 0xFF 1_000 #\\101)\n"
+        ));
+    }
+    program
+}
+
+/// Makes a program that can't be read, either because a form is invalid, like a misplaced dot,
+/// or because the grammar gives up, like on an unknown escape, both at the very end so that the
+/// whole input is read first.
+fn erroneous(forms: usize, grammar: bool) -> String {
+    let mut program = mixed(forms);
+    program.push_str(match grammar {
+        true => "(f \"\\q\")",
+        false => "(f 1 . 2 . 3)",
+    });
+    program
+}
+
+/// Runs the function for about a second, giving back the average time it took.
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while start.elapsed() < Duration::from_secs(1) {
+        std::hint::black_box(f());
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+fn main() {
+    for (name, make) in [
+        ("class", corpus as fn(usize) -> String),
+        ("mixed", mixed),
+        ("invalid form", |n| erroneous(n, false)),
+        ("grammar error", |n| erroneous(n, true)),
+    ] {
+        for size in [10, 100, 1000] {
+            let input = make(size);
+            assert_eq!(
+                format!("{:?}", FastParser::parse_all(&input)),
+                format!("{:?}", SexprParser::parse_all(&input))
+            );
+            let pest = time(|| SexprParser::parse_all(&input));
+            let fast = time(|| FastParser::parse_all(&input));
+            println!(
+                "{:>13} {:>7} bytes: pest {:>10.2?}, fast {:>10.2?}, {:.1}x faster",
+                name,
+                input.len(),
+                pest,
+                fast,
+                pest.as_secs_f64() / fast.as_secs_f64()
+            );
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::sync::Arc;
+
+use crate::{
+    errors::BracketError,
+    sexpr::{line_breaks, Source, TokInfo},
+};
+
+/// Finds the brackets of the given part of the input, with their offsets. Only the brackets that
+/// are part of the code are returned, so the ones in strings, comments, chars like `#\(` and
+/// symbols like `|(|` are skipped.
+pub(crate) fn scan_brackets(input: &str, start: usize, end: usize) -> Vec<(usize, char)> {
+    scan_brackets_resumable(input, start, end).0
+}
+
+/// Like `scan_brackets`, also giving back where the scan can go on from once more input is added
+/// after `end`. That's the start of the last string, comment or other token that reaches `end`,
+/// as it could go on past it, or `end` if there is none.
+pub(crate) fn scan_brackets_resumable(
+    input: &str,
+    start: usize,
+    end: usize,
+) -> (Vec<(usize, char)>, usize) {
+    let bytes = &input.as_bytes()[..end];
+    let at = |i: usize, s: &str| bytes[i..].starts_with(s.as_bytes());
+    // skips to the first byte after `i` that is a char boundary
+    let next_char = |i: usize| {
+        let mut i = i + 1;
+        while i < end && !input.is_char_boundary(i) {
+            i += 1;
+        }
+        i
+    };
+    let skip_line = |mut i: usize, continuations: bool| {
+        while i < end && !matches!(bytes[i], b'\n' | b'\r') {
+            i += match bytes[i] {
+                b'\\' if continuations && at(i + 1, "\r\n") => 3,
+                b'\\' if continuations => 2,
+                _ => 1,
+            };
+        }
+        i
+    };
+
+    let mut brackets = Vec::new();
+    let mut resume = end;
+    let mut i = start;
+    while i < end {
+        let next = match bytes[i] {
+            b'(' | b'[' | b'{' | b')' | b']' | b'}' => {
+                brackets.push((i, bytes[i] as char));
+                i += 1;
+                continue;
+            }
+            b';' => skip_line(i, false),
+            b'"' => {
+                let mut i = i + 1;
+                while i < end && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i + 1
+            }
+            b'|' => match bytes[i + 1..].iter().position(|&b| b == b'|') {
+                Some(len) => i + len + 2,
+                None => end,
+            },
+            b'\\' => next_char(i + 1),
+            b'#' if at(i, "#|") => {
+                let mut depth = 0;
+                let mut i = i;
+                while i < end {
+                    if at(i, "#|") {
+                        depth += 1;
+                        i += 2;
+                    } else if at(i, "|#") {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                i
+            }
+            // the `;` of `#;` doesn't start a line comment
+            b'#' if at(i, "#;") => i + 2,
+            b'#' if at(i, "#\\") => next_char(i + 2),
+            b'#' if at(i, "#!eof") => end,
+            b'#' if at(i, "#! ") || at(i, "#!/") => skip_line(i, true),
+            b'#' if at(i, "#<<") => here_string_end(input, i, end).unwrap_or(end),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        if next >= end {
+            resume = i;
+        }
+        i = next;
+    }
+    (brackets, resume)
+}
+
+/// Finds the end of the here string at `start`, which goes on until a line that is just the
+/// terminator after its `#<<`, or `None` if there is no such line before `end`. Like in the
+/// grammar, the body is either empty or starts with a line that isn't the closing one, so a
+/// here string with an empty line right before the closing one isn't one.
+pub(crate) fn here_string_end(input: &str, start: usize, end: usize) -> Option<usize> {
+    let line_end = input[start..end]
+        .find(['\n', '\r'])
+        .map_or(end, |len| start + len);
+    let terminator = &input[start + 3..line_end];
+    let body = &input[line_end..end];
+    let mut lines = line_breaks(body);
+    let first = lines.next()?;
+    let closes = |line: usize| {
+        let rest = body[line..].strip_prefix(terminator).unwrap_or("\0");
+        rest.is_empty() || rest.starts_with(['\n', '\r'])
+    };
+    if closes(first) {
+        return Some(line_end + first + terminator.len());
+    }
+    let closing = lines.find(|&line| closes(line))?;
+    let empty = matches!(&body[first..closing], "\n" | "\r" | "\r\n");
+    (!empty).then_some(line_end + closing + terminator.len())
+}
+
+/// Gets the closing bracket that matches the given opening one.
+fn closer_of(opener: char) -> char {
+    match opener {
+        '(' => ')',
+        '[' => ']',
+        _ => '}',
+    }
+}
+
+/// Checks that the brackets of the given part of the input are balanced, giving back the first
+/// bracket that isn't. When several brackets are left open, the error is about the outermost one.
+pub(crate) fn find_bracket_error(
+    source: &Arc<Source>,
+    start: usize,
+    end: usize,
+) -> Option<BracketError> {
+    let input = source.text();
+    let tok = |offset: usize, len: usize| TokInfo::new(source.clone(), offset, offset + len);
+    let mut open: Vec<(usize, char)> = Vec::new();
+    for (offset, bracket) in scan_brackets(input, start, end) {
+        if matches!(bracket, '(' | '[' | '{') {
+            open.push((offset, bracket));
+            continue;
+        }
+        match open.pop() {
+            Some((opener, expected)) if closer_of(expected) != bracket => {
+                return Some(BracketError {
+                    opener: Some(tok(opener, 1)),
+                    expected: Some(closer_of(expected)),
+                    closer: Some(tok(offset, 1)),
+                    missing_at: missing_closer(input, opener, offset).map(|at| tok(at, 0)),
+                });
+            }
+            Some(_) => {}
+            None => {
+                return Some(BracketError {
+                    opener: None,
+                    expected: None,
+                    closer: Some(tok(offset, 1)),
+                    missing_at: None,
+                });
+            }
+        }
+    }
+    open.first().map(|&(opener, expected)| BracketError {
+        opener: Some(tok(opener, 1)),
+        expected: Some(closer_of(expected)),
+        closer: None,
+        // the line that gives away the missing closer can be past the end
+        missing_at: missing_closer(input, opener, input.len()).map(|at| tok(at, 0)),
+    })
+}
+
+/// Guesses where the closer of the given opener is missing, going by the indentation. The first
+/// line before `limit` that is indented as much as the opener or less can't be part of what the
+/// opener encloses, so the closer most likely belongs at the end of the line of code before it.
+fn missing_closer(input: &str, opener: usize, limit: usize) -> Option<usize> {
+    let line_start = line_breaks(&input[..opener]).last().unwrap_or(0);
+    let opener_indent = input[line_start..opener].chars().count();
+    let mut last_code_end = None;
+    let lines = line_breaks(input).skip_while(|&start| start <= opener);
+    for line_start in lines.take_while(|&start| start < limit) {
+        let line_end = input[line_start..]
+            .find(['\n', '\r'])
+            .map_or(input.len(), |i| line_start + i);
+        let line = &input[line_start..line_end];
+        let code = code_before_comment(line).trim_end();
+        let indent = code.len() - code.trim_start().len();
+        if !code.trim_start().is_empty() {
+            let first = code.trim_start().chars().next().unwrap();
+            if input[line_start..line_start + indent].chars().count() <= opener_indent
+                && !matches!(first, ')' | ']' | '}')
+            {
+                return last_code_end;
+            }
+            last_code_end = Some(line_start + code.len());
+        }
+    }
+    None
+}
+
+/// Gets the part of a line of code before its line comment, if it has one. A `;` isn't one in
+/// a string, a symbol between pipes like `|a;b|`, or after a backslash, like in `#\;`.
+pub(crate) fn code_before_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut in_pipes = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            // a backslash is just a char between pipes
+            '\\' if !in_pipes => escaped = true,
+            '"' if !in_pipes => in_string = !in_string,
+            '|' if !in_string => in_pipes = !in_pipes,
+            ';' if !in_string && !in_pipes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod brackets_tests {
+    use std::sync::Arc;
+
+    use super::{find_bracket_error, scan_brackets};
+    use crate::{errors::BracketError, sexpr::Source};
+
+    fn bracket_error(input: &str) -> Option<BracketError> {
+        find_bracket_error(&Arc::new(Source::new(input)), 0, input.len())
+    }
+
+    fn brackets(input: &str) -> String {
+        scan_brackets(input, 0, input.len())
+            .into_iter()
+            .map(|(_, c)| c)
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_skips_non_code() {
+        assert_eq!(brackets("(a [b] {c})"), "([]{})");
+        assert_eq!(brackets(r#"("(" #\( #\) |)| a\( #"[" #rx"(")"#), "()");
+        assert_eq!(brackets("(; (\n#| ( #| ) |# ( |# )"), "()");
+        assert_eq!(brackets("(#<<EOF\n(\n  EOF\nEOF\n)"), "()");
+        assert_eq!(brackets("(#<<EOF\nEOF\n)"), "()");
+        // an empty line can't be the whole body, so the here string isn't closed
+        assert_eq!(brackets("(#<<EOF\n\nEOF\n)"), "(");
+        assert_eq!(brackets("#! (\n() #!eof ("), "()");
+        // a form commented out with `#;` is still code, unlike the rest of a `;` comment
+        assert_eq!(brackets("#;(a\n b) #;|(| [c]"), "()[]");
+    }
+
+    #[test]
+    fn test_balanced() {
+        let input = "(define (f x)\n  [list x \")\"])";
+        assert!(bracket_error(input).is_none());
+    }
+
+    #[test]
+    fn test_mismatched() {
+        let input = "(bla [bla)]";
+        match bracket_error(input) {
+            Some(BracketError {
+                opener: Some(opener),
+                expected: Some(']'),
+                closer: Some(closer),
+                missing_at: None,
+            }) => {
+                assert_eq!((opener.as_str(), opener.start_line_col()), ("[", (1, 6)));
+                assert_eq!((closer.as_str(), closer.start_line_col()), (")", (1, 10)));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unclosed_with_indentation_hint() {
+        let input = "(define (f x)\n  (if x ; a comment\n      1\n  2) ; done\n\n(define y 2)";
+        match bracket_error(input) {
+            Some(BracketError {
+                opener: Some(opener),
+                expected: Some(')'),
+                closer: None,
+                missing_at: Some(missing),
+            }) => {
+                assert_eq!(opener.start_line_col(), (1, 1));
+                assert_eq!(missing.start_line_col(), (4, 5));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_indentation_hint_on_any_line() {
+        // the `;` of a char or of a symbol between pipes doesn't start a comment, and the lines
+        // can end in any line break
+        for (input, at) in [
+            ("(define (f x)\n  (f #\\;\n(define y 2)", (2, 9)),
+            (
+                "(define (f x)\n  (f |a;b| \"c;\") ; d\n(define y 2)",
+                (2, 17),
+            ),
+            ("(define (f x)\r  (f x)\r\n\r(define y 2)", (2, 8)),
+        ] {
+            match bracket_error(input) {
+                Some(BracketError {
+                    missing_at: Some(missing),
+                    ..
+                }) => assert_eq!(missing.start_line_col(), at, "{:?}", input),
+                other => panic!("{:?} in {:?}", other, input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unclosed_without_hint() {
+        let input = "(define (f x)\n  (+ x 1)";
+        match bracket_error(input) {
+            Some(BracketError {
+                opener: Some(opener),
+                closer: None,
+                missing_at: None,
+                ..
+            }) => assert_eq!(opener.start_line_col(), (1, 1)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_stray_closer() {
+        let input = "(a)\n  b)";
+        match bracket_error(input) {
+            Some(BracketError {
+                opener: None,
+                expected: None,
+                closer: Some(closer),
+                ..
+            }) => assert_eq!(closer.start_line_col(), (2, 4)),
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::ops::Range;
+
+use crate::{
+    cst::{Cst, CstElement, CstNode, NodeKind, TriviaKind},
+    errors::ParsingError,
+    sexpr::{Sexpr, TokInfo},
+};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CommentKind {
+    Line,    // ; comment
+    Block,   // #| comment |#
+    Sexpr,   // #;(commented out code)
+    Shebang, // #! comment
+    Eof,     // #!eof and everything after it
+}
+
+/// A comment of the input. The text is the content of the comment, without the `;`, `#|` and
+/// `|#` or `#;` around it, while the `TokInfo` has the whole comment.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Comment {
+    pub kind: CommentKind,
+    pub text: String,
+    pub tok: TokInfo,
+}
+
+/// Reads every comment of the input, in order.
+pub fn parse_comments(input: &str) -> Result<Vec<Comment>, ParsingError> {
+    Ok(Cst::parse(input)?.comments())
+}
+
+impl Cst {
+    /// Gets every comment, in order. The comments inside of a sexpr comment are part of it, so
+    /// they aren't given on their own.
+    pub fn comments(&self) -> Vec<Comment> {
+        let mut comments = Vec::new();
+        collect_comments(&self.root, &mut comments);
+        comments
+    }
+}
+
+fn collect_comments(node: &CstNode, comments: &mut Vec<Comment>) {
+    for child in &node.children {
+        match child {
+            CstElement::Node(node) if node.kind == NodeKind::SexprComment => {
+                let text = node.nodes().next().map_or(String::new(), |n| n.to_string());
+                comments.push(Comment {
+                    kind: CommentKind::Sexpr,
+                    text,
+                    tok: node.tok.clone(),
+                });
+            }
+            CstElement::Node(node) => collect_comments(node, comments),
+            CstElement::Trivia(kind, tok) => {
+                let s = tok.as_str();
+                let (kind, text) = match kind {
+                    TriviaKind::Whitespace | TriviaKind::ByteOrderMark => continue,
+                    TriviaKind::LineComment => (CommentKind::Line, s.trim_start_matches(';')),
+                    TriviaKind::BlockComment => {
+                        let s = s.strip_prefix("#|").unwrap_or(s);
+                        (CommentKind::Block, s.strip_suffix("|#").unwrap_or(s))
+                    }
+                    TriviaKind::ShebangComment => {
+                        (CommentKind::Shebang, s.strip_prefix("#!").unwrap_or(s))
+                    }
+                    TriviaKind::EofComment => {
+                        (CommentKind::Eof, s.strip_prefix("#!eof").unwrap_or(s))
+                    }
+                };
+                comments.push(Comment {
+                    kind,
+                    text: text.trim().to_string(),
+                    tok: tok.clone(),
+                });
+            }
+            CstElement::Token(_) => {}
+        }
+    }
+}
+
+/// Groups the given comments with the top-level form that follows them. A comment leads a form
+/// when it's after the previous form and before the form, without being on the same line as the
+/// end of the previous form, as then it's a comment about that one.
+pub fn leading_comments<'a>(
+    comments: &'a [Comment],
+    forms: &[Sexpr<TokInfo>],
+) -> Vec<Vec<&'a Comment>> {
+    (0..forms.len())
+        .map(|i| comments[leading_range(comments, forms, i)].iter().collect())
+        .collect()
+}
+
+/// Like `leading_comments`, giving the range of `comments` that leads the form at `index`, as the
+/// comments and the forms are both in order. The comments inside of the form before it are
+/// skipped.
+pub(crate) fn leading_range(
+    comments: &[Comment],
+    forms: &[Sexpr<TokInfo>],
+    index: usize,
+) -> Range<usize> {
+    let tok = forms[index].get_decorator();
+    let prev_end = index.checked_sub(1).map(|i| forms[i].get_decorator());
+    let after_prev = prev_end.map_or(0, |prev| {
+        comments.partition_point(|c| c.tok.start() < prev.end())
+    });
+    let before = comments.partition_point(|c| c.tok.end() <= tok.start());
+    // the comments at the end of the line of the previous form go with it
+    let on_prev_line = |c: &&Comment| {
+        prev_end.is_some_and(|prev| c.tok.start_line_col().0 == prev.end_line_col().0)
+    };
+    let start = after_prev
+        + comments[after_prev..before]
+            .iter()
+            .take_while(on_prev_line)
+            .count();
+    start..before
+}
+
+#[cfg(test)]
+mod comments_tests {
+    use super::{leading_comments, parse_comments, CommentKind};
+    use crate::{hashlang::strip_hashlang, sexpr_parser::SexprParser};
+
+    const SOURCE: &str = "#lang racket\n\
+        ;; Number -> Number\n\
+        ;; adds one to n\n\
+        (define (add1 n) (+ n 1)) ; done\n\
+        \n\
+        #| old version |#\n\
+        #;(define (add1 n)\n  ; inside\n  n)\n\
+        (define x #| the answer |# 42)\n";
+
+    #[test]
+    fn test_parse_comments() {
+        let comments = parse_comments(SOURCE).unwrap();
+        let found: Vec<_> = comments
+            .iter()
+            .map(|c| (c.kind, c.text.as_str(), c.tok.start_line_col()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (CommentKind::Line, "Number -> Number", (2, 1)),
+                (CommentKind::Line, "adds one to n", (3, 1)),
+                (CommentKind::Line, "done", (4, 27)),
+                (CommentKind::Block, "old version", (6, 1)),
+                (
+                    CommentKind::Sexpr,
+                    "(define (add1 n)\n  ; inside\n  n)",
+                    (7, 1)
+                ),
+                (CommentKind::Block, "the answer", (10, 11)),
+            ]
+        );
+        assert_eq!(comments[0].tok.as_str(), ";; Number -> Number");
+        assert_eq!(comments[3].tok.as_str(), "#| old version |#");
+    }
+
+    #[test]
+    fn test_leading_comments() {
+        let mut input = SOURCE.to_string();
+        strip_hashlang(&mut input);
+        let comments = parse_comments(&input).unwrap();
+        let forms = SexprParser::parse_all(&input).unwrap();
+        let leading = leading_comments(&comments, &forms);
+        let texts: Vec<Vec<_>> = leading
+            .iter()
+            .map(|cs| cs.iter().map(|c| c.text.as_str()).collect())
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                vec!["Number -> Number", "adds one to n"],
+                vec!["old version", "(define (add1 n)\n  ; inside\n  n)"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unusual_comments() {
+        // a shebang comment goes on in the next line after a backslash, even before a \r\n
+        let comments = parse_comments("#! \\\r\n#|").unwrap();
+        let found: Vec<_> = comments.iter().map(|c| (c.kind, c.text.as_str())).collect();
+        assert_eq!(found, [(CommentKind::Shebang, "\\\r\n#|")]);
+        assert!(parse_comments("#|#|#;\u{feff}").is_err());
+    }
+}
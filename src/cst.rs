@@ -0,0 +1,670 @@
+use std::sync::Arc;
+
+use pest::{iterators::Pair, Parser, Span};
+
+use crate::{
+    errors::ParsingError,
+    sexpr::{Sexpr, Source, TokInfo},
+    sexpr_parser::{Elements, ReaderOptions, Rule, SexprParser},
+};
+
+/// A concrete syntax tree of a whole input. Unlike `Sexpr`, it keeps every byte of the input,
+/// so that it can be printed back exactly as it was read.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Cst {
+    pub root: CstNode,
+}
+
+/// A node of the concrete syntax tree. The text of the node is the text of its children, in order.
+#[derive(PartialEq, Debug, Clone)]
+pub struct CstNode {
+    pub kind: NodeKind,
+    pub tok: TokInfo,
+    pub children: Vec<CstElement>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum CstElement {
+    Node(CstNode),
+    /// A bracket or a prefix, like `(`, `#hash(` or `'`, or the whole lexeme of an atom.
+    Token(TokInfo),
+    /// Whitespace or a comment.
+    Trivia(TriviaKind, TokInfo),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum NodeKind {
+    Program,
+    List(Bracket),
+    Vector(Bracket),
+    Hash(Bracket),
+    Prefab(Bracket),
+    Abbreviation, // a quote-like prefix and the sexpr after it, like 'a, #,@a or #&a
+    SexprComment, // a commented out sexpr, like #;(a b)
+    Atom,         // a single token, like a symbol, a number or a string
+    Dot,
+}
+
+/// The bracket a list-like node was written with.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Bracket {
+    Round,
+    Square,
+    Curly,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TriviaKind {
+    Whitespace,
+    LineComment,    // ; comment, without the newline ending it
+    BlockComment,   // #| comment |#
+    ShebangComment, // #! comment, or #!/usr/bin/racket
+    EofComment,     // #!eof and everything after it
+    ByteOrderMark,  // the U+FEFF that some editors save at the start of a file
+}
+
+impl Cst {
+    /// Reads the whole input into a concrete syntax tree, within the limits of the default
+    /// `ReaderOptions`.
+    pub fn parse(input: &str) -> Result<Cst, ParsingError> {
+        Self::parse_with(input, &ReaderOptions::default())
+    }
+
+    /// Like `parse`, with the given reader options. The input is checked like
+    /// `SexprParser::parse_all_with` does, so it's an error if a hash table has an item that
+    /// isn't a pair, for example, even though it could be kept in the tree.
+    pub fn parse_with(input: &str, opts: &ReaderOptions) -> Result<Cst, ParsingError> {
+        Self::parse_with_sexprs(input, opts).map(|(cst, _)| cst)
+    }
+
+    /// Like `parse_with`, also giving back the S-expressions of the tree that it was checked
+    /// with, the ones `to_sexprs_with` gives.
+    pub fn parse_with_sexprs(
+        input: &str,
+        opts: &ReaderOptions,
+    ) -> Result<(Cst, Vec<Sexpr<TokInfo>>), ParsingError> {
+        let source = Arc::new(Source::new(input));
+        let (program, base) = SexprParser::parse_program(&source, 0, input.len(), opts)?;
+        let program = program.into_iter().next().unwrap();
+        let mut root = build_node(program, NodeKind::Program, &source, base);
+        // the grammar reads what comes after the byte order mark, which is kept as trivia
+        if base > 0 {
+            let tok = TokInfo::new(source.clone(), 0, base);
+            root.children
+                .insert(0, CstElement::Trivia(TriviaKind::ByteOrderMark, tok));
+            root.tok = TokInfo::new(source, 0, input.len());
+        }
+        let cst = Cst { root };
+        let forms = cst.to_sexprs_with(opts)?;
+        Ok((cst, forms))
+    }
+
+    /// Gets the top-level forms, including the ones commented out with `#;`.
+    pub fn forms(&self) -> impl Iterator<Item = &CstNode> {
+        self.root.nodes()
+    }
+
+    /// Converts the tree into the S-expressions it stands for, like `SexprParser::parse_all`.
+    pub fn to_sexprs(&self) -> Result<Vec<Sexpr<TokInfo>>, ParsingError> {
+        self.to_sexprs_with(&ReaderOptions::default())
+    }
+
+    /// Like `to_sexprs`, with the given reader options. The S-expressions have the positions of
+    /// the tree, which are the ones of the input it was read from.
+    pub fn to_sexprs_with(
+        &self,
+        opts: &ReaderOptions,
+    ) -> Result<Vec<Sexpr<TokInfo>>, ParsingError> {
+        opts.check_size(self.root.tok.as_str().len())?;
+        let mut count = 0;
+        for node in self.forms() {
+            check_limits(node, 1, &mut count, opts)?;
+        }
+        let mut forms = Vec::new();
+        for node in self.forms() {
+            match to_sexpr(node, opts) {
+                Err(ParsingError::NothingToParse) => {}
+                form => forms.push(form?),
+            }
+        }
+        Ok(forms)
+    }
+}
+
+impl CstNode {
+    /// Gets the child nodes, skipping the tokens and the trivia.
+    pub fn nodes(&self) -> impl Iterator<Item = &CstNode> {
+        self.children.iter().filter_map(|child| match child {
+            CstElement::Node(node) => Some(node),
+            _ => None,
+        })
+    }
+}
+
+/// Checks the node and the ones in it against the limits of the options, like the reader does
+/// before it reads them, so the commented out forms count too. The node is at the given depth.
+fn check_limits(
+    node: &CstNode,
+    depth: usize,
+    count: &mut usize,
+    opts: &ReaderOptions,
+) -> Result<(), ParsingError> {
+    if node.kind == NodeKind::Dot {
+        return Ok(());
+    }
+    *count += 1;
+    if depth > opts.max_depth {
+        // like the reader, blame the opening bracket or prefix of the node, if it has one
+        let tok = match node.children.first() {
+            Some(CstElement::Token(tok)) => tok,
+            _ => &node.tok,
+        };
+        return Err(ParsingError::TooDeep(tok.clone()));
+    }
+    if *count > opts.max_forms {
+        return Err(ParsingError::TooManyForms(node.tok.clone()));
+    }
+    node.nodes()
+        .try_for_each(|child| check_limits(child, depth + 1, count, opts))
+}
+
+/// Converts the node into the S-expression it stands for, with the same checks as the reader. A
+/// sexpr comment stands for nothing, which is a `NothingToParse` error, like for the reader.
+fn to_sexpr(node: &CstNode, opts: &ReaderOptions) -> Result<Sexpr<TokInfo>, ParsingError> {
+    let tok = node.tok.clone();
+    let opening = match node.children.first() {
+        Some(CstElement::Token(opening)) => opening.as_str(),
+        _ => "",
+    };
+    let (items, dots) = match node.kind {
+        NodeKind::Atom => return SexprParser::read_atom(&tok, opts),
+        NodeKind::SexprComment => return Err(ParsingError::NothingToParse),
+        NodeKind::Abbreviation => {
+            let form = to_sexpr(node.nodes().next().unwrap(), opts)?;
+            let inner = form.get_decorator().clone();
+            let atom = SexprParser::abbreviation(prefix_rule(opening), form, inner);
+            return Ok(Sexpr::Atom(atom, tok));
+        }
+        _ => {
+            // the bracket is the last char of the opening, like in `#hash[`
+            let at = tok.start() - tok.source().offset() + opening.len();
+            let bracket = TokInfo::new(tok.source().clone(), at - 1, at);
+            SexprParser::check_bracket(bracket, opts)?;
+            elements(node, opts)?
+        }
+    };
+    let prefix = &opening[..opening.len() - 1];
+    match node.kind {
+        NodeKind::Vector(_) => {
+            let len = Some(&prefix[1..])
+                .filter(|len| !len.is_empty())
+                .map(|len| len.parse().ok());
+            SexprParser::build_vector(items, dots, len, tok)
+        }
+        NodeKind::Hash(_) => {
+            SexprParser::build_hash(SexprParser::hash_kind(prefix), items, dots, tok)
+        }
+        NodeKind::Prefab(_) => SexprParser::build_prefab(items, dots, tok),
+        _ => SexprParser::build_list(items, dots, tok),
+    }
+}
+
+/// Converts the nodes in a list-like node, skipping the sexpr comments. The dots are given with
+/// the number of items before each of them.
+fn elements(node: &CstNode, opts: &ReaderOptions) -> Result<Elements, ParsingError> {
+    let mut items = Vec::new();
+    let mut dots = Vec::new();
+    for child in node.nodes() {
+        match child.kind {
+            NodeKind::Dot => dots.push((items.len(), child.tok.clone())),
+            NodeKind::SexprComment => {}
+            _ => items.push(to_sexpr(child, opts)?),
+        }
+    }
+    Ok((items, dots))
+}
+
+/// Gets the rule of a prefix like `'` or `#,@`.
+fn prefix_rule(prefix: &str) -> Rule {
+    match prefix {
+        "'" => Rule::quoted,
+        "`" => Rule::quasiquoted,
+        "," => Rule::unquoted,
+        ",@" => Rule::unquote_spliced,
+        "#'" => Rule::syntax_quoted,
+        "#`" => Rule::quasisyntax_quoted,
+        "#," => Rule::unsyntaxed,
+        "#,@" => Rule::unsyntax_spliced,
+        _ => Rule::boxed,
+    }
+}
+
+/// Builds the node of the given pair, whose spans are relative to `base`. The significant
+/// children become nodes, and the text in between them is split into tokens and trivia.
+fn build_node(pair: Pair<Rule>, kind: NodeKind, source: &Arc<Source>, base: usize) -> CstNode {
+    let span = pair.as_span();
+    let tok = |span: Span| TokInfo::new(source.clone(), base + span.start(), base + span.end());
+    let mut children = Vec::new();
+    let mut offset = base + span.start();
+    let opening = opening_len(&pair);
+    if opening > 0 {
+        children.push(CstElement::Token(TokInfo::new(
+            source.clone(),
+            offset,
+            offset + opening,
+        )));
+        offset += opening;
+    }
+    for child in significant_children(pair) {
+        let child_span = child.as_span();
+        split_gap(source, offset, base + child_span.start(), &mut children);
+        offset = base + child_span.end();
+        let node = match child.as_rule() {
+            Rule::dot => leaf(tok(child_span), NodeKind::Dot),
+            Rule::atom => {
+                let inner = child.into_inner().next().unwrap();
+                match inner.as_rule() {
+                    Rule::sexpr_comment => build_node(inner, NodeKind::SexprComment, source, base),
+                    Rule::quoted
+                    | Rule::quasiquoted
+                    | Rule::unquote_spliced
+                    | Rule::unquoted
+                    | Rule::syntax_quoted
+                    | Rule::quasisyntax_quoted
+                    | Rule::unsyntax_spliced
+                    | Rule::unsyntaxed
+                    | Rule::boxed => build_node(inner, NodeKind::Abbreviation, source, base),
+                    _ => leaf(tok(child_span), NodeKind::Atom),
+                }
+            }
+            rule => {
+                // the bracket is the last char of the opening, like in `#hash[`
+                let opening = child_span
+                    .as_str()
+                    .trim_start_matches(|c| !"([{".contains(c));
+                let bracket = match opening.chars().next() {
+                    Some('[') => Bracket::Square,
+                    Some('{') => Bracket::Curly,
+                    _ => Bracket::Round,
+                };
+                let kind = match rule {
+                    Rule::vector => NodeKind::Vector(bracket),
+                    Rule::hash => NodeKind::Hash(bracket),
+                    Rule::prefab => NodeKind::Prefab(bracket),
+                    _ => NodeKind::List(bracket),
+                };
+                build_node(child, kind, source, base)
+            }
+        };
+        children.push(CstElement::Node(node));
+    }
+    split_gap(source, offset, base + span.end(), &mut children);
+    CstNode {
+        kind,
+        tok: tok(span),
+        children,
+    }
+}
+
+fn leaf(tok: TokInfo, kind: NodeKind) -> CstNode {
+    CstNode {
+        kind,
+        tok: tok.clone(),
+        children: vec![CstElement::Token(tok)],
+    }
+}
+
+/// Gets the pairs that become nodes, which are the sexprs and dots. The `sexpr` pairs are only
+/// wrappers, and the items of vectors, hashes and prefabs are taken out of their `vector_items`.
+fn significant_children(pair: Pair<Rule>) -> Vec<Pair<Rule>> {
+    let mut children = Vec::new();
+    for child in pair.into_inner() {
+        match child.as_rule() {
+            Rule::sexpr => children.push(child.into_inner().next().unwrap()),
+            Rule::vector_items => children.extend(significant_children(child)),
+            Rule::dot => children.push(child),
+            // the rest, like vector_len, hash_kind and EOI, are part of the tokens
+            _ => {}
+        }
+    }
+    children
+}
+
+/// Gets the length of the bracket or prefix that the given pair starts with, like `#hash(` or `'`.
+fn opening_len(pair: &Pair<Rule>) -> usize {
+    match pair.as_rule() {
+        Rule::slist | Rule::vector | Rule::hash | Rule::prefab => {
+            pair.as_str().find(['(', '[', '{']).unwrap() + 1
+        }
+        Rule::quoted | Rule::quasiquoted | Rule::unquoted => 1,
+        Rule::unsyntax_spliced => 3,
+        Rule::program | Rule::atom | Rule::dot => 0,
+        // ,@ #' #` #, #& and #;
+        _ => 2,
+    }
+}
+
+/// Splits the text in between two nodes, or between the opening of a node and its first node,
+/// into trivia, as the grammar reads it, and the closing bracket of the node, if there is one.
+fn split_gap(source: &Arc<Source>, start: usize, end: usize, children: &mut Vec<CstElement>) {
+    let gap = &source.text()[start..end];
+    // the trivia rule can't fail, as it can match nothing
+    let trivia = SexprParser::parse(Rule::leading_trivia, gap)
+        .unwrap()
+        .next()
+        .unwrap();
+    let trivia_end = trivia.as_str().len();
+    let mut offset = 0;
+    let mut push = |kind, from: usize, to: usize| {
+        let tok = TokInfo::new(source.clone(), start + from, start + to);
+        children.push(match kind {
+            Some(kind) => CstElement::Trivia(kind, tok),
+            None => CstElement::Token(tok),
+        });
+    };
+    for comment in trivia.into_inner() {
+        let span = comment.as_span();
+        if span.start() > offset {
+            push(Some(TriviaKind::Whitespace), offset, span.start());
+        }
+        let kind = match comment.as_rule() {
+            Rule::line_comment => TriviaKind::LineComment,
+            Rule::block_comment => TriviaKind::BlockComment,
+            Rule::shebang_comment => TriviaKind::ShebangComment,
+            _ => TriviaKind::EofComment,
+        };
+        push(Some(kind), span.start(), span.end());
+        offset = span.end();
+    }
+    if trivia_end > offset {
+        push(Some(TriviaKind::Whitespace), offset, trivia_end);
+    }
+    if trivia_end < gap.len() {
+        push(None, trivia_end, gap.len());
+    }
+}
+
+impl std::fmt::Display for Cst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.root)
+    }
+}
+
+impl std::fmt::Display for CstNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.children
+            .iter()
+            .try_for_each(|child| write!(f, "{}", child))
+    }
+}
+
+impl std::fmt::Display for CstElement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CstElement::Node(node) => write!(f, "{}", node),
+            CstElement::Token(tok) | CstElement::Trivia(_, tok) => write!(f, "{}", tok.as_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod cst_tests {
+    use pest::Parser;
+
+    use super::{Bracket, Cst, CstElement, CstNode, NodeKind, TriviaKind};
+    use crate::{
+        sexpr::TokInfo,
+        sexpr_parser::{ReaderOptions, Rule, SexprParser},
+        test_util::random_inputs,
+    };
+
+    const SOURCE: &str = "#lang racket ; the language\r\n\
+        #| a #| nested |# block |#\n\
+        (define [f x]  {+ x #x1F}) #;(commented out)\n\
+        '(a . b) #3(1) #hash[(a . 1)] #s(p 1) #&x #,@y\n\
+        (λ \"a\\x41\\n\" #\\space #<<EOF\n  (here\nEOF\n  )\n\
+        ; trailing comment\n";
+
+    #[test]
+    fn test_round_trip() {
+        for input in [
+            SOURCE,
+            "",
+            "  ",
+            "a",
+            "(a)\n#!eof (",
+            "#! shebang \\\n ( \n(a #|c|#)",
+            "\u{feff}(a)\r\n(b)\r; done",
+        ] {
+            let cst = Cst::parse(input).unwrap();
+            assert_eq!(cst.to_string(), input);
+            assert_eq!(cst.root.tok.as_str(), input);
+        }
+    }
+
+    #[test]
+    fn test_forms() {
+        let cst = Cst::parse(SOURCE).unwrap();
+        let kinds: Vec<_> = cst.forms().map(|f| f.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                NodeKind::Atom,
+                NodeKind::Atom,
+                NodeKind::List(Bracket::Round),
+                NodeKind::SexprComment,
+                NodeKind::Abbreviation,
+                NodeKind::Vector(Bracket::Round),
+                NodeKind::Hash(Bracket::Square),
+                NodeKind::Prefab(Bracket::Round),
+                NodeKind::Abbreviation,
+                NodeKind::Abbreviation,
+                NodeKind::List(Bracket::Round),
+            ]
+        );
+        let define = cst.forms().nth(2).unwrap();
+        let nodes: Vec<_> = define.nodes().map(|n| (n.kind, n.to_string())).collect();
+        assert_eq!(
+            nodes,
+            [
+                (NodeKind::Atom, "define".to_string()),
+                (NodeKind::List(Bracket::Square), "[f x]".to_string()),
+                (NodeKind::List(Bracket::Curly), "{+ x #x1F}".to_string()),
+            ]
+        );
+        let tokens: Vec<_> = define
+            .children
+            .iter()
+            .map(|child| match child {
+                CstElement::Node(_) => "node",
+                CstElement::Token(tok) => tok.as_str(),
+                CstElement::Trivia(TriviaKind::Whitespace, _) => "ws",
+                CstElement::Trivia(..) => "comment",
+            })
+            .collect();
+        assert_eq!(tokens, ["(", "node", "ws", "node", "ws", "node", ")"]);
+    }
+
+    #[test]
+    fn test_trivia() {
+        let cst = Cst::parse(SOURCE).unwrap();
+        let trivia: Vec<_> = cst
+            .root
+            .children
+            .iter()
+            .filter_map(|child| match child {
+                CstElement::Trivia(kind, tok) if *kind != TriviaKind::Whitespace => {
+                    Some((*kind, tok.as_str(), tok.start_line_col()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            trivia,
+            [
+                (TriviaKind::LineComment, "; the language", (1, 14)),
+                (
+                    TriviaKind::BlockComment,
+                    "#| a #| nested |# block |#",
+                    (2, 1)
+                ),
+                (TriviaKind::LineComment, "; trailing comment", (9, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shebang_continuation() {
+        // the comment goes on in the next line after a backslash, even before a \r\n
+        let cst = Cst::parse("#! a \\\r\n#| b\r\n(c)").unwrap();
+        let children: Vec<_> = cst
+            .root
+            .children
+            .iter()
+            .map(|child| match child {
+                CstElement::Trivia(kind, _) => (Some(*kind), child.to_string()),
+                _ => (None, child.to_string()),
+            })
+            .collect();
+        assert_eq!(
+            children,
+            [
+                (
+                    Some(TriviaKind::ShebangComment),
+                    "#! a \\\r\n#| b".to_string()
+                ),
+                (Some(TriviaKind::Whitespace), "\r\n".to_string()),
+                (None, "(c)".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        let cst = Cst::parse("\u{feff}(a)\r(b)").unwrap();
+        let trivia = match &cst.root.children[0] {
+            CstElement::Trivia(kind, tok) => (*kind, tok.start(), tok.end()),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(trivia, (TriviaKind::ByteOrderMark, 0, 3));
+        let starts: Vec<_> = cst.forms().map(|f| f.tok.start_line_col()).collect();
+        assert_eq!(starts, [(1, 2), (2, 1)]);
+    }
+
+    #[test]
+    fn test_atoms_keep_lexeme() {
+        let cst = Cst::parse("(#x1F 1/2 |a b| #\\space \"\\x41\")").unwrap();
+        let list = cst.forms().next().unwrap();
+        let lexemes: Vec<_> = list.nodes().map(|n| n.tok.as_str()).collect();
+        assert_eq!(lexemes, ["#x1F", "1/2", "|a b|", "#\\space", "\"\\x41\""]);
+    }
+
+    #[test]
+    fn test_to_sexprs() {
+        let cst = Cst::parse(SOURCE).unwrap();
+        assert_eq!(
+            cst.to_sexprs().unwrap(),
+            SexprParser::parse_all(SOURCE).unwrap()
+        );
+        let (read, forms) = Cst::parse_with_sexprs(SOURCE, &ReaderOptions::default()).unwrap();
+        assert_eq!(read.to_string(), SOURCE);
+        assert_eq!(forms, cst.to_sexprs().unwrap());
+    }
+
+    #[test]
+    fn test_random_trivia() {
+        let pieces = [
+            "(", ")", "[", "]", "#(", "#2(", "#hash(", "#s(", "'", ",@", "#,@", "#&", "#;", "a",
+            "1", ".", " ", "\t", "\n", "\r", "\r\n", "\\", "|", ";", "#|", "|#", "#! ", "#!/",
+            "#!eof", "\"", "\u{feff}",
+        ];
+        for input in random_inputs(&pieces, 0x2545f4914f6cdd1d, 5000, 20) {
+            let Ok(cst) = Cst::parse(&input) else {
+                assert!(SexprParser::parse_all(&input).is_err(), "{:?}", input);
+                continue;
+            };
+            assert_eq!(cst.to_string(), input);
+            let parsed = SexprParser::parse_all(&input);
+            assert_eq!(
+                format!("{:?}", cst.to_sexprs()),
+                format!("{:?}", parsed),
+                "{:?}",
+                input
+            );
+            let mut trivia = Vec::new();
+            collect_trivia(&cst.root, &mut trivia);
+            for (kind, tok) in trivia {
+                // each comment is as long as the grammar reads it in the input
+                let rule = match kind {
+                    TriviaKind::Whitespace => {
+                        assert!(
+                            tok.as_str().chars().all(|c| " \r\n".contains(c)),
+                            "{:?}",
+                            input
+                        );
+                        continue;
+                    }
+                    TriviaKind::ByteOrderMark => continue,
+                    TriviaKind::LineComment => Rule::line_comment,
+                    TriviaKind::BlockComment => Rule::block_comment,
+                    TriviaKind::ShebangComment => Rule::shebang_comment,
+                    TriviaKind::EofComment => Rule::eof_comment,
+                };
+                let read = SexprParser::parse(rule, &input[tok.start()..]);
+                let len = read.map_or(0, |pairs| pairs.as_str().len());
+                assert_eq!(len, tok.as_str().len(), "{:?} in {:?}", kind, input);
+            }
+        }
+    }
+
+    fn collect_trivia(node: &CstNode, trivia: &mut Vec<(TriviaKind, TokInfo)>) {
+        for child in &node.children {
+            match child {
+                CstElement::Node(node) => collect_trivia(node, trivia),
+                CstElement::Trivia(kind, tok) => trivia.push((*kind, tok.clone())),
+                CstElement::Token(_) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Cst::parse("(a").is_err());
+        assert!(Cst::parse("(a))").is_err());
+        // the same as the reader, even though the grammar reads them
+        for input in ["#hash(a)", "(. a)", "#(a . b)", "#s()", "#1(a b)"] {
+            assert_eq!(
+                format!("{:?}", Cst::parse(input).unwrap_err()),
+                format!("{:?}", SexprParser::parse_all(input).unwrap_err()),
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_with_options() {
+        let opts = ReaderOptions {
+            square_brackets: false,
+            max_depth: 2,
+            ..ReaderOptions::default()
+        };
+        for input in ["[a]", "((a))", "#;((a)) b"] {
+            assert_eq!(
+                format!("{:?}", Cst::parse_with(input, &opts).unwrap_err()),
+                format!(
+                    "{:?}",
+                    SexprParser::parse_all_with(input, &opts).unwrap_err()
+                ),
+                "{:?}",
+                input
+            );
+        }
+        let cst = Cst::parse("((a) #s(p [1]))").unwrap();
+        assert!(cst.to_sexprs_with(&opts).is_err());
+        assert_eq!(
+            cst.to_sexprs().unwrap(),
+            SexprParser::parse_all("((a) #s(p [1]))").unwrap()
+        );
+    }
+}
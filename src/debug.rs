@@ -1,17 +1,28 @@
-use std::{io::BufRead, vec};
+use std::io::BufRead;
 
 use racket_wellformed::sexpr_parser::SexprParser;
 
 pub fn main() {
+    // with --stream, every form is printed as soon as it's read, which works for piped input
+    if std::env::args().any(|arg| arg == "--stream") {
+        for form in SexprParser::forms(std::io::stdin().lock()) {
+            match form {
+                Ok(expr) => println!("{}", expr),
+                Err(e) => println!("Error: {}", e),
+            }
+        }
+        return;
+    }
+
     // repl
     let mut buf = String::new();
     for line in std::io::stdin().lock().lines() {
         let line = line.unwrap();
         if line.starts_with("!RUN") {
-            let output = SexprParser::parse_all(&buf).unwrap_or_else(|e| {
+            let (output, errors) = SexprParser::parse_all_recovering(&buf);
+            for e in errors {
                 println!("Error: {}", e);
-                vec![]
-            });
+            }
             for expr in output {
                 println!("{}", expr);
             }
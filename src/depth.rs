@@ -0,0 +1,541 @@
+use std::sync::Arc;
+
+use pest::unicode::ALPHABETIC;
+
+use crate::{
+    brackets::here_string_end,
+    errors::ParsingError,
+    sexpr::{Source, TokInfo},
+    sexpr_parser::{ReaderOptions, Rule},
+};
+
+/// Checks that the forms of `source.text()[start..end]` aren't nested deeper than `max_depth`,
+/// nor its block comments, reading them like the given rule of the grammar does, which is
+/// `program`, `leading_trivia`, or `sexpr` for any other. The readers check that before they
+/// read anything, as pest nests a call for every nested form or comment, and can't be trusted
+/// to stop before it runs out of stack.
+pub(crate) fn check_depth(
+    source: &Arc<Source>,
+    start: usize,
+    end: usize,
+    rule: Rule,
+    opts: &ReaderOptions,
+) -> Result<(), ParsingError> {
+    let text = &source.text()[..end];
+    let scan = DepthScan {
+        text,
+        bytes: text.as_bytes(),
+        max_depth: opts.max_depth,
+    };
+    let scanned = match rule {
+        Rule::program => scan.program(start),
+        Rule::leading_trivia => scan.skip(start).map(|_| ()),
+        _ => scan.form(start, 1).map(|_| ()),
+    };
+    scanned.map_err(|(start, end)| ParsingError::TooDeep(TokInfo::new(source.clone(), start, end)))
+}
+
+/// What a part of the scan gives back, unless it found a token nested too deep, in which case
+/// it stops with the offsets of that token. The first token of a form is blamed, which is its
+/// opening bracket or prefix, or the whole atom.
+type Scan<T> = Result<T, (usize, usize)>;
+
+/// Goes through the tokens of `sexpr.pest` without reading them, so that it only keeps track
+/// of how deep they're nested. It follows where each token ends exactly, as taking a part of a
+/// symbol for a comment or the other way around would hide the brackets after it. What the
+/// grammar can't read is where the scan stops, as the grammar doesn't go past it either. The
+/// scan allocates nothing, and only recurses once for every form it's in, up to `max_depth`.
+struct DepthScan<'a> {
+    text: &'a str,
+    bytes: &'a [u8],
+    max_depth: usize,
+}
+
+/// The prefixes of the forms, like `'` in `'x`, in the order of the grammar.
+const PREFIXES: [&str; 10] = ["'", "`", ",@", ",", "#'", "#`", "#,@", "#,", "#&", "#;"];
+
+const CHAR_NAMES: [&str; 12] = [
+    "null",
+    "nul",
+    "backspace",
+    "tab",
+    "newline",
+    "linefeed",
+    "vtab",
+    "page",
+    "return",
+    "space",
+    "rubout",
+    "delete",
+];
+
+impl DepthScan<'_> {
+    fn at(&self, i: usize, s: &str) -> bool {
+        self.bytes[i.min(self.bytes.len())..].starts_with(s.as_bytes())
+    }
+
+    fn at_insensitive(&self, i: usize, s: &str) -> Option<usize> {
+        let rest = self.bytes.get(i..i + s.len())?;
+        rest.eq_ignore_ascii_case(s.as_bytes())
+            .then_some(i + s.len())
+    }
+
+    /// Skips the char at `i`, which can take more than one byte.
+    fn next_char(&self, i: usize) -> usize {
+        self.text[i..]
+            .chars()
+            .next()
+            .map_or(i, |c| i + c.len_utf8())
+    }
+
+    fn is_alphabetic(&self, i: usize) -> bool {
+        self.text[i..].chars().next().is_some_and(ALPHABETIC)
+    }
+
+    /// `&delimiter`, which is what a number has to be followed by.
+    fn is_delimiter(&self, i: usize) -> bool {
+        self.bytes
+            .get(i)
+            .is_none_or(|b| b" \n\r([{)]}\";'`,".contains(b))
+    }
+
+    fn check(&self, depth: usize, start: usize, end: usize) -> Scan<()> {
+        match depth > self.max_depth {
+            true => Err((start, end)),
+            false => Ok(()),
+        }
+    }
+
+    /// `program = { SOI ~ sexpr* ~ EOI }`
+    fn program(&self, i: usize) -> Scan<()> {
+        let mut i = self.skip(i)?;
+        while let Some(end) = self.form(i, 1)? {
+            i = self.skip(end)?;
+        }
+        Ok(())
+    }
+
+    /// Goes through the form at `i`, which is at the given depth, giving back where it ends, or
+    /// `None` if the grammar can't read a form there.
+    fn form(&self, i: usize, depth: usize) -> Scan<Option<usize>> {
+        if let Some(open) = self.opening(i) {
+            self.check(depth, i, open)?;
+            return self.items(open, depth);
+        }
+        // like in the grammar, a shorter prefix is tried when there's no form after a longer one,
+        // like `,` in `,@`, and without any the prefix is the start of a keyword or symbol
+        let mut prefixed = false;
+        for end in PREFIXES
+            .iter()
+            .filter(|p| self.at(i, p))
+            .map(|p| i + p.len())
+        {
+            self.check(depth, i, end)?;
+            if let Some(end) = self.form(self.skip(end)?, depth + 1)? {
+                return Ok(Some(end));
+            }
+            prefixed = true;
+        }
+        if prefixed {
+            return Ok(self.keyword_or_symbol(i));
+        }
+        let end = self.atom(i);
+        if let Some(end) = end {
+            self.check(depth, i, end)?;
+        }
+        Ok(end)
+    }
+
+    /// Finds the end of the opening of a list, vector, hash table or prefab struct at `i`, like
+    /// `(` or `#hash[`.
+    fn opening(&self, i: usize) -> Option<usize> {
+        let open =
+            |j: usize| matches!(self.bytes.get(j), Some(b'(' | b'[' | b'{')).then_some(j + 1);
+        if self.bytes.get(i) != Some(&b'#') {
+            return open(i);
+        }
+        let digits = self.bytes[i + 1..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit());
+        if let Some(end) = open(i + 1 + digits.count()) {
+            return Some(end);
+        }
+        // like in the grammar, the first kind of hash table that matches is the only one tried
+        let hash = ["#hasheqv", "#hasheq", "#hashalw", "#hash"];
+        if let Some(kind) = hash.iter().find(|kind| self.at(i, kind)) {
+            return open(i + kind.len());
+        }
+        self.at(i, "#s").then(|| open(i + 2)).flatten()
+    }
+
+    /// Goes through the items after the opening bracket that ends at `open`, giving back where
+    /// the closing bracket ends, if it's the one that matches.
+    fn items(&self, open: usize, depth: usize) -> Scan<Option<usize>> {
+        let close = match self.bytes[open - 1] {
+            b'(' => b')',
+            b'[' => b']',
+            _ => b'}',
+        };
+        let mut i = self.skip(open)?;
+        loop {
+            if self.at(i, ".")
+                && (self.is_delimiter(i + 1) || self.at(i + 1, "#|") || self.at(i + 1, "#;"))
+            {
+                i = self.skip(i + 1)?;
+                continue;
+            }
+            match self.form(i, depth + 1)? {
+                Some(end) => i = self.skip(end)?,
+                None => break,
+            }
+        }
+        Ok((self.bytes.get(i) == Some(&close)).then_some(i + 1))
+    }
+
+    /// Finds the end of the atom at `i`, trying the kinds of atoms in the order of the grammar.
+    fn atom(&self, i: usize) -> Option<usize> {
+        if let Some(end) = (self.boolean(i))
+            .or_else(|| self.character(i))
+            .or_else(|| self.number(i))
+            .or_else(|| self.legacy_number(i))
+        {
+            return Some(end);
+        }
+        let string_at = |start: usize| match self.at(start, "\"") {
+            true => self.string(start),
+            false => None,
+        };
+        let regexp = match self.at(i, "#rx") || self.at(i, "#px") {
+            true => string_at(i + 3).or_else(|| string_at(i + 4).filter(|_| self.at(i + 3, "#"))),
+            false => None,
+        };
+        if let Some(end) = string_at(i)
+            .or_else(|| string_at(i + 1).filter(|_| self.at(i, "#")))
+            .or(regexp)
+        {
+            return Some(end);
+        }
+        if self.at(i, "#<<") {
+            // the terminator can't be empty
+            let terminated = !matches!(self.bytes.get(i + 3), None | Some(b'\n' | b'\r'));
+            return terminated
+                .then(|| here_string_end(self.text, i, self.bytes.len()))
+                .flatten();
+        }
+        self.keyword_or_symbol(i)
+    }
+
+    /// `keyword | symbol`, which are the last kinds of atoms.
+    fn keyword_or_symbol(&self, i: usize) -> Option<usize> {
+        match self.at(i, "#:") {
+            true => self.symbol(i + 2).or_else(|| self.symbol(i)),
+            false => self.symbol(i),
+        }
+    }
+
+    /// `bool = { bool_false | bool_true }`
+    fn boolean(&self, i: usize) -> Option<usize> {
+        ["#false", "#f", "#true", "#t"]
+            .iter()
+            .find(|b| self.at(i, b))
+            .map(|b| i + b.len())
+    }
+
+    /// `character = ${ "#\\" ~ (char_named | char_octal | char_unicode | char_any) }`
+    fn character(&self, i: usize) -> Option<usize> {
+        if !self.at(i, "#\\") || i + 2 >= self.bytes.len() {
+            return None;
+        }
+        let start = i + 2;
+        let named = CHAR_NAMES.iter().find_map(|name| {
+            let end = self.at_insensitive(start, name)?;
+            (!self.is_alphabetic(end)).then_some(end)
+        });
+        let octal = self.bytes[start..].iter().take(3);
+        let octal = (octal.filter(|b| (b'0'..=b'7').contains(b)).count() == 3).then_some(start + 3);
+        let unicode = |prefix: &str, max: usize| {
+            let hex = self.bytes[start + 1..].iter().take(max);
+            let hex = hex.take_while(|b| b.is_ascii_hexdigit()).count();
+            (self.at(start, prefix) && hex > 0).then_some(start + 1 + hex)
+        };
+        let any = self.next_char(start);
+        let any = (!self.is_alphabetic(start) || !self.is_alphabetic(any)).then_some(any);
+        named
+            .or(octal)
+            .or_else(|| unicode("u", 4))
+            .or_else(|| unicode("U", 8))
+            .or(any)
+    }
+
+    /// `string = ${ "\"" ~ (raw_string | escape)* ~ "\"" }`, which is also the rest of a byte
+    /// string after its `#`, as they have the same escapes.
+    fn string(&self, i: usize) -> Option<usize> {
+        let mut i = i + 1;
+        while i < self.bytes.len() {
+            match self.bytes[i] {
+                b'"' => return Some(i + 1),
+                b'\\' => i = self.escape(i + 1)?,
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    /// `escape = ${ "\\" ~ (predefined | octal | byte | legacy_unicode | unicode |
+    /// continuation) }`, from after the backslash. Only the start of an escape has to be
+    /// matched, as the rest of it would be read as a raw part of the string anyway.
+    fn escape(&self, i: usize) -> Option<usize> {
+        let hex = |j: usize| self.bytes.get(j).is_some_and(u8::is_ascii_hexdigit);
+        match *self.bytes.get(i)? {
+            b'a' | b'b' | b't' | b'n' | b'v' | b'f' | b'r' | b'e' | b'"' | b'\'' | b'\\' => {
+                Some(i + 1)
+            }
+            b'0'..=b'7' | b'\n' | b'\r' => Some(i + 1),
+            b'u' if self.at(i + 1, "{") => {
+                let len = (1..=6).take_while(|&len| hex(i + 1 + len)).count();
+                (len > 0 && self.at(i + 2 + len, "}")).then_some(i + 3 + len)
+            }
+            b'x' | b'u' | b'U' => hex(i + 1).then_some(i + 2),
+            _ => None,
+        }
+    }
+
+    /// `symbol = ${ !("#<<" | "#|") ~ (symbol_plain | symbol_pipe | symbol_escape)+ }`
+    fn symbol(&self, i: usize) -> Option<usize> {
+        if self.at(i, "#<<") || self.at(i, "#|") {
+            return None;
+        }
+        let mut end = i;
+        while let Some(&b) = self.bytes.get(end) {
+            end = match b {
+                b' ' | b'\n' | b'\r' | b'(' | b')' | b'[' | b']' | b'{' | b'}' | b'"' => break,
+                b'#' if self.at(end, "#;") => break,
+                b'|' => match self.bytes[end + 1..].iter().position(|&b| b == b'|') {
+                    Some(len) => end + len + 2,
+                    None => break,
+                },
+                b'\\' if end + 1 < self.bytes.len() => self.next_char(end + 1),
+                b'\\' => break,
+                _ => self.next_char(end),
+            };
+        }
+        (end > i).then_some(end)
+    }
+
+    /// `number = ${ (... ~ complex2 | ... ~ complex8 | ... ~ complex16 | ... ~ complex10) ~
+    /// &delimiter }`, where each radix has its prefix and an optional exactness, in any order
+    fn number(&self, i: usize) -> Option<usize> {
+        let exactness = |i| {
+            self.at_insensitive(i, "#e")
+                .or_else(|| self.at_insensitive(i, "#i"))
+        };
+        let end = [(2, "#b"), (8, "#o"), (16, "#x"), (10, "#d")]
+            .into_iter()
+            .find_map(|(radix, prefix)| {
+                let start = if let Some(end) = self.at_insensitive(i, prefix) {
+                    exactness(end).unwrap_or(end)
+                } else if let Some(end) = exactness(i) {
+                    match self.at_insensitive(end, prefix) {
+                        Some(start) => start,
+                        // the radix is optional after an exactness only in radix 10
+                        None if radix == 10 => end,
+                        None => return None,
+                    }
+                } else if radix == 10 {
+                    i
+                } else {
+                    return None;
+                };
+                self.complex(radix, start)
+            })?;
+        self.is_delimiter(end).then_some(end)
+    }
+
+    /// `complex10 = ${ real10 ~ "@" ~ real10 | real10 ~ imag10 | imag10 | real10 }`
+    fn complex(&self, radix: u32, i: usize) -> Option<usize> {
+        let polar = self.real(radix, i).filter(|&mid| self.at(mid, "@"));
+        if let Some(end) = polar.and_then(|mid| self.real(radix, mid + 1)) {
+            return Some(end);
+        }
+        if let Some(end) = self.real(radix, i).and_then(|mid| self.imag(radix, mid)) {
+            return Some(end);
+        }
+        self.imag(radix, i).or_else(|| self.real(radix, i))
+    }
+
+    /// `real10 = @{ inf_nan | sign? ~ ureal10 }`
+    fn real(&self, radix: u32, i: usize) -> Option<usize> {
+        self.inf_nan(i)
+            .or_else(|| self.ureal(radix, self.sign(i).unwrap_or(i)))
+    }
+
+    /// `imag10 = @{ (inf_nan | sign ~ ureal10?) ~ ^"i" }`
+    fn imag(&self, radix: u32, i: usize) -> Option<usize> {
+        let end = match self.inf_nan(i) {
+            Some(end) => end,
+            None => {
+                let start = self.sign(i)?;
+                self.ureal(radix, start).unwrap_or(start)
+            }
+        };
+        self.at_insensitive(end, "i")
+    }
+
+    /// `inf_nan = _{ sign ~ (^"inf" | ^"nan") ~ "." ~ ("0" | ^"f") }`
+    fn inf_nan(&self, i: usize) -> Option<usize> {
+        let start = self.sign(i)?;
+        let dot =
+            (self.at_insensitive(start, "inf")).or_else(|| self.at_insensitive(start, "nan"))?;
+        self.at_insensitive(dot, ".0")
+            .or_else(|| self.at_insensitive(dot, ".f"))
+    }
+
+    fn sign(&self, i: usize) -> Option<usize> {
+        matches!(self.bytes.get(i), Some(b'+' | b'-')).then_some(i + 1)
+    }
+
+    /// `ureal10 = _{ decimal10 | uinteger10 ~ "/" ~ uinteger10 | uinteger10 }`
+    fn ureal(&self, radix: u32, i: usize) -> Option<usize> {
+        if let Some(end) = self.decimal(radix, i) {
+            return Some(end);
+        }
+        let end = self.uinteger(radix, i)?;
+        let denominator = self.at(end, "/").then(|| self.uinteger(radix, end + 1));
+        Some(denominator.flatten().unwrap_or(end))
+    }
+
+    /// `uinteger10 = _{ digit10+ ~ "#"* }`
+    fn uinteger(&self, radix: u32, i: usize) -> Option<usize> {
+        Some(self.hashes(self.digits(radix, i)?))
+    }
+
+    /// `decimal10 = _{ digit10+ ~ "." ~ digit10* ~ "#"* ~ exp10? | "." ~ digit10+ ~ "#"* ~
+    /// exp10? | digit10+ ~ "#"+ ~ "." ~ "#"* ~ exp10? | uinteger10 ~ exp10 }`
+    fn decimal(&self, radix: u32, i: usize) -> Option<usize> {
+        let with_exp = |end| self.exp(radix, end).unwrap_or(end);
+        let Some(digits) = self.digits(radix, i) else {
+            let end = self.digits(radix, i + usize::from(self.at(i, ".")))?;
+            return self.at(i, ".").then(|| with_exp(self.hashes(end)));
+        };
+        if self.at(digits, ".") {
+            let end = self.digits(radix, digits + 1).unwrap_or(digits + 1);
+            return Some(with_exp(self.hashes(end)));
+        }
+        let hashes = self.hashes(digits);
+        if hashes > digits && self.at(hashes, ".") {
+            return Some(with_exp(self.hashes(hashes + 1)));
+        }
+        self.exp(radix, hashes)
+    }
+
+    /// `exp10 = _{ exp_mark10 ~ sign? ~ digit10+ }`, where radix 16 only has the exponent
+    /// markers that aren't digits
+    fn exp(&self, radix: u32, i: usize) -> Option<usize> {
+        let marks: &[u8] = if radix == 16 { b"sl" } else { b"sldef" };
+        if !marks.contains(&self.bytes.get(i)?.to_ascii_lowercase()) {
+            return None;
+        }
+        self.digits(radix, self.sign(i + 1).unwrap_or(i + 1))
+    }
+
+    fn digits(&self, radix: u32, i: usize) -> Option<usize> {
+        let rest = self.bytes.get(i..)?;
+        let len = rest.iter().take_while(|&&b| char::from(b).is_digit(radix));
+        Some(i + len.count()).filter(|&end| end > i)
+    }
+
+    fn hashes(&self, i: usize) -> usize {
+        i + self.bytes[i..].iter().take_while(|&&b| b == b'#').count()
+    }
+
+    /// `legacy_number = ${ (legacy_hex | legacy_float | legacy_integer) ~ &delimiter }`
+    fn legacy_number(&self, i: usize) -> Option<usize> {
+        let hex = self.at(i, "0x").then(|| {
+            let len = self.bytes[i + 2..]
+                .iter()
+                .take_while(|b| b.is_ascii_hexdigit());
+            Some(i + 2 + len.count()).filter(|&end| end > i + 2)
+        });
+        let signed = i + usize::from(self.at(i, "-"));
+        let float = || match self.legacy_digits(signed) {
+            Some(end) if self.at(end, ".") => Some(self.legacy_digits(end + 1).unwrap_or(end + 1)),
+            Some(end) => self.at(end, "f").then_some(end + 1),
+            None => self
+                .at(signed, ".")
+                .then(|| self.legacy_digits(signed + 1))
+                .flatten(),
+        };
+        let end = hex
+            .flatten()
+            .or_else(float)
+            .or_else(|| self.legacy_digits(signed))?;
+        self.is_delimiter(end).then_some(end)
+    }
+
+    /// `legacy_digits = _{ ASCII_DIGIT ~ (ASCII_DIGIT | "_")* }`
+    fn legacy_digits(&self, i: usize) -> Option<usize> {
+        if !self.bytes.get(i)?.is_ascii_digit() {
+            return None;
+        }
+        let len = self.bytes[i..]
+            .iter()
+            .take_while(|&&b| b.is_ascii_digit() || b == b'_');
+        Some(i + len.count())
+    }
+
+    /// Skips the whitespace and the comments at `i`, like pest does in between the parts of the
+    /// rules that aren't atomic.
+    fn skip(&self, mut i: usize) -> Scan<usize> {
+        loop {
+            i = match self.bytes.get(i) {
+                Some(b' ' | b'\n' | b'\r') => i + 1,
+                Some(b';') => self.line_end(i, false),
+                _ if self.at(i, "#|") => match self.block_comment(i)? {
+                    Some(end) => end,
+                    None => return Ok(i),
+                },
+                _ if self.at(i, "#!eof") => self.bytes.len(),
+                _ if self.at(i, "#! ") || self.at(i, "#!/") => self.line_end(i, true),
+                _ => return Ok(i),
+            };
+        }
+    }
+
+    /// `block_comment = @{ "#|" ~ (block_comment | !("|#" | "#|") ~ ANY)* ~ "|#" }`, which is
+    /// too deep like the forms are when it's nested deeper than `max_depth`. The grammar nests
+    /// as deep in a comment that isn't closed, so that's checked before it gives up on it.
+    fn block_comment(&self, i: usize) -> Scan<Option<usize>> {
+        let mut depth = 0;
+        let mut i = i;
+        while i < self.bytes.len() {
+            if self.at(i, "#|") {
+                depth += 1;
+                self.check(depth, i, i + 2)?;
+                i += 2;
+            } else if self.at(i, "|#") {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return Ok(Some(i));
+                }
+            } else {
+                i += 1;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Finds the end of a line comment, right before the end of its line. The line of a `#!`
+    /// comment goes on after a backslash right before the end of the line.
+    fn line_end(&self, mut i: usize, continuations: bool) -> usize {
+        while let Some(&b) = self.bytes.get(i) {
+            i += match b {
+                b'\n' | b'\r' => break,
+                b'\\' if continuations && self.at(i + 1, "\r\n") => 3,
+                b'\\' if continuations && (self.at(i + 1, "\n") || self.at(i + 1, "\r")) => 2,
+                _ => 1,
+            };
+        }
+        i
+    }
+}
@@ -0,0 +1,397 @@
+use std::{ops::Range, sync::Arc};
+
+use crate::{
+    errors::ParsingError,
+    sexpr::{Sexpr, Source, TokInfo},
+    sexpr_parser::{ReaderOptions, SexprParser},
+};
+
+/// A text kept parsed as it's edited, like the buffer of an editor. An edit only reparses the
+/// top-level forms on the lines it touches, while the forms after it are just moved to their new
+/// positions.
+/// While the text has errors, every edit reparses all of it, recovering from the errors.
+pub struct Document {
+    source: Arc<Source>,
+    opts: ReaderOptions,
+    forms: Vec<Sexpr<TokInfo>>,
+    errors: Vec<ParsingError>,
+}
+
+/// What an edit changed: the forms at the indexes `old` before the edit were replaced by the
+/// ones at the indexes `new` after it. The forms around them are the same, only moved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+impl Document {
+    pub fn new(text: &str) -> Self {
+        Self::new_with(text, &ReaderOptions::default())
+    }
+
+    pub fn new_with(text: &str, opts: &ReaderOptions) -> Self {
+        let mut doc = Document {
+            source: Arc::new(Source::new(text)),
+            opts: opts.clone(),
+            forms: Vec::new(),
+            errors: Vec::new(),
+        };
+        doc.reparse_all(0);
+        doc
+    }
+
+    pub fn text(&self) -> &str {
+        self.source.text()
+    }
+
+    /// The source of the current text, which also converts between byte offsets and the
+    /// positions of an editor, like `source().offset_of(line, col, PositionEncoding::Utf16)`.
+    pub fn source(&self) -> &Arc<Source> {
+        &self.source
+    }
+
+    /// The top-level forms, with their tokens in the source of the current text.
+    pub fn forms(&self) -> &[Sexpr<TokInfo>] {
+        &self.forms
+    }
+
+    /// The errors of the current text, which is empty when all of it could be parsed.
+    pub fn errors(&self) -> &[ParsingError] {
+        &self.errors
+    }
+
+    /// Replaces the byte range `range` of the text with `replacement`, and reparses what it
+    /// touched. Panics if the range is out of the text or not on char boundaries, like
+    /// `String::replace_range`.
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> Change {
+        let mut text = self.text().to_string();
+        text.replace_range(range.clone(), replacement);
+        let source = Arc::new(Source::new(&text));
+        let old_len = self.text().len();
+        let change = if self.errors.is_empty() {
+            self.reparse_around(&source, range, old_len)
+        } else {
+            None
+        };
+        self.source = source;
+        change.unwrap_or_else(|| self.reparse_all(old_len))
+    }
+
+    /// Reparses the forms on the lines of the edit of `range`, along with the form on each side
+    /// of them. Those two must be read the same as before, otherwise the edit reaches them too,
+    /// like when it opens a string or adds a `'` before a form, and the region grows to include
+    /// them.
+    /// Gives back `None` when the region can't be parsed even when it has all of the text.
+    fn reparse_around(
+        &mut self,
+        source: &Arc<Source>,
+        range: Range<usize>,
+        old_len: usize,
+    ) -> Option<Change> {
+        let new_len = source.text().len();
+        let moved = |offset: usize| offset + new_len - old_len;
+        let span = |form: &Sexpr<TokInfo>| {
+            let tok = form.get_decorator();
+            (tok.start(), tok.end())
+        };
+        // the forms touching the edit, even just at its ends, are reparsed, along with the rest
+        // of its lines, as a `;` comments out the rest of its line
+        let text = self.text();
+        let line_start = text[..range.start].rfind(['\n', '\r']).map_or(0, |i| i + 1);
+        let line_end = text[range.end..]
+            .find(['\n', '\r'])
+            .map_or(old_len, |i| range.end + i);
+        let mut first = self.forms.partition_point(|f| span(f).1 < line_start);
+        let mut last = self.forms.partition_point(|f| span(f).0 <= line_end);
+        let (lo, hi, reparsed) = loop {
+            // a form ending on the line of the one before the region, like the symbol `,;`, can
+            // be read again as a `,` and a comment running over it, so it's reparsed too
+            while first > 1 {
+                let guard = span(&self.forms[first - 1]).0;
+                let guard_line = text[..guard].rfind(['\n', '\r']).map_or(0, |i| i + 1);
+                if span(&self.forms[first - 2]).1 <= guard_line {
+                    break;
+                }
+                first -= 1;
+            }
+            let before = first.checked_sub(1).map(|i| span(&self.forms[i]));
+            let after = self.forms.get(last).map(|f| {
+                let (start, end) = span(f);
+                (moved(start), moved(end))
+            });
+            let start = before.map_or(0, |(start, _)| start);
+            let end = after.map_or(new_len, |(_, end)| end);
+            let guards = before.is_some() as usize + after.is_some() as usize;
+            match SexprParser::parse_range(source, start, end, &self.opts) {
+                Ok(forms) if forms.len() >= guards => {
+                    let same_before = before.is_none_or(|s| span(&forms[0]) == s);
+                    let same_after = after.is_none_or(|s| span(&forms[forms.len() - 1]) == s);
+                    if same_before && same_after {
+                        break (
+                            first - before.is_some() as usize,
+                            last + after.is_some() as usize,
+                            forms,
+                        );
+                    }
+                    if !same_before {
+                        first -= 1;
+                    }
+                    if !same_after {
+                        last += 1;
+                    }
+                }
+                _ if after.is_some() => last += 1,
+                _ if before.is_some() => first -= 1,
+                _ => return None,
+            }
+        };
+
+        // the forms before the region keep their offsets, as the text is the same up to them
+        let after: Vec<_> = self.forms.drain(hi..).collect();
+        self.forms.truncate(lo);
+        self.forms = (std::mem::take(&mut self.forms).into_iter())
+            .map(|form| retag(form, source, |offset| offset))
+            .collect();
+        // the forms read as guards are still counted as unchanged
+        let guards = (first - lo) + (hi - last);
+        let new = first..first + reparsed.len() - guards;
+        self.forms.extend(reparsed);
+        self.forms
+            .extend(after.into_iter().map(|form| retag(form, source, moved)));
+        Some(Change {
+            old: first..last,
+            new,
+        })
+    }
+
+    /// Reparses the whole text, recovering from its errors if it has some, and finds what changed
+    /// by comparing the forms at each end.
+    fn reparse_all(&mut self, old_len: usize) -> Change {
+        let new_len = self.text().len();
+        let (forms, errors) = match SexprParser::parse_range(&self.source, 0, new_len, &self.opts) {
+            Ok(forms) => (forms, Vec::new()),
+            Err(_) => SexprParser::parse_range_recovering(&self.source, 0, new_len, &self.opts),
+        };
+        let same = |a: &Sexpr<TokInfo>, b: &Sexpr<TokInfo>, from_end: bool| {
+            let (a, b) = (a.get_decorator(), b.get_decorator());
+            let (a_start, b_start) = if from_end {
+                (old_len - a.start(), new_len - b.start())
+            } else {
+                (a.start(), b.start())
+            };
+            a_start == b_start && a.as_str() == b.as_str()
+        };
+        let prefix = self
+            .forms
+            .iter()
+            .zip(&forms)
+            .take_while(|(a, b)| same(a, b, false))
+            .count();
+        let suffix = self.forms[prefix..]
+            .iter()
+            .rev()
+            .zip(forms[prefix..].iter().rev())
+            .take_while(|(a, b)| same(a, b, true))
+            .count();
+        let change = Change {
+            old: prefix..self.forms.len() - suffix,
+            new: prefix..forms.len() - suffix,
+        };
+        self.forms = forms;
+        self.errors = errors;
+        change
+    }
+}
+
+/// Moves the tokens of a form to the new source, at the offsets given by `moved`.
+fn retag(
+    form: Sexpr<TokInfo>,
+    source: &Arc<Source>,
+    moved: impl Fn(usize) -> usize,
+) -> Sexpr<TokInfo> {
+    form.map_tag(&mut |tok: TokInfo| {
+        TokInfo::new(source.clone(), moved(tok.start()), moved(tok.end()))
+    })
+}
+
+#[cfg(test)]
+mod document_tests {
+    use std::sync::Arc;
+
+    use super::{Change, Document};
+    use crate::{sexpr_parser::SexprParser, test_util::Rng};
+
+    /// Checks that the document has the same forms as parsing its text from scratch.
+    fn assert_reparsed(doc: &Document) {
+        match SexprParser::parse_all(doc.text()) {
+            Ok(forms) => {
+                assert_eq!(doc.forms(), forms, "{:?}", doc.text());
+                assert!(doc.errors().is_empty());
+            }
+            Err(_) => {
+                let (forms, errors) = SexprParser::parse_all_recovering(doc.text());
+                assert_eq!(doc.forms(), forms, "{:?}", doc.text());
+                assert_eq!(doc.errors().len(), errors.len(), "{:?}", doc.text());
+            }
+        }
+        for form in doc.forms() {
+            let tok = form.get_decorator();
+            assert_eq!(tok.as_str(), &doc.text()[tok.start()..tok.end()]);
+        }
+        assert!(in_source(doc), "{:?}", doc.text());
+    }
+
+    /// Whether every token of the document is in its current source.
+    fn in_source(doc: &Document) -> bool {
+        let mut all = true;
+        for form in doc.forms() {
+            form.clone().map_tag(&mut |tok| {
+                all &= Arc::ptr_eq(tok.source(), doc.source());
+                tok
+            });
+        }
+        all
+    }
+
+    #[test]
+    fn test_only_touched_forms_change() {
+        let mut doc = Document::new("(define x 1)\n(define y 2)\n(f x y)\n");
+        let change = doc.edit(23..24, "20");
+        assert_eq!(
+            change,
+            Change {
+                old: 1..2,
+                new: 1..2
+            }
+        );
+        assert_eq!(doc.text(), "(define x 1)\n(define y 20)\n(f x y)\n");
+        assert_eq!(doc.forms()[2].get_decorator().start_line_col(), (3, 1));
+        assert_eq!(doc.forms()[2].get_decorator().start(), 27);
+        assert_reparsed(&doc);
+
+        // the forms on either side of the one read again with the edit are moved to the new text
+        let mut doc = Document::new("(a)\n(b)\n(c 1)\n(d)\n(e)\n");
+        let old_source = doc.source().clone();
+        doc.edit(11..12, "10");
+        let source = |i: usize| doc.forms()[i].get_decorator().source();
+        assert!(!Arc::ptr_eq(source(0), &old_source));
+        assert!(Arc::ptr_eq(source(0), doc.source()));
+        assert!(Arc::ptr_eq(source(4), doc.source()));
+        assert_reparsed(&doc);
+    }
+
+    #[test]
+    fn test_edits_between_forms() {
+        let mut doc = Document::new("(a)\n\n\n(b)");
+        assert_eq!(
+            doc.edit(4..4, "(new)"),
+            Change {
+                old: 1..1,
+                new: 1..2
+            }
+        );
+        assert_reparsed(&doc);
+        // a space between forms that doesn't touch them changes nothing
+        assert_eq!(
+            doc.edit(10..10, " "),
+            Change {
+                old: 2..2,
+                new: 2..2
+            }
+        );
+        assert_reparsed(&doc);
+        // removing the space between two forms reparses both, even though they stay the same
+        assert_eq!(
+            doc.edit(3..4, ""),
+            Change {
+                old: 0..2,
+                new: 0..2
+            }
+        );
+        assert_reparsed(&doc);
+    }
+
+    #[test]
+    fn test_edits_that_reach_later_forms() {
+        for (text, range, replacement) in [
+            ("(a) (b) (c)", 1..1, "\""),     // an unclosed string takes the rest
+            ("(a) (b) (c) \"", 1..1, "\""),  // and closing it gives the forms back
+            ("(a) (b) (c)", 2..3, ""),       // an unclosed bracket
+            ("(a) (b) (c)", 3..4, "#| "),    // an unclosed block comment
+            ("(a) (b) (c)", 3..3, " #!eof"), // the rest is a comment
+            ("(a) (b) (c)", 3..3, " '"),     // a prefix quotes the next form
+            ("(a) (b) (c)", 3..3, " #;"),    // or comments it out
+            ("(a) '", 5..5, "(b)"),          // a prefix at the end
+            ("(a) ' ", 6..6, "(b)"),
+            ("(a) (b)", 3..3, "\n#! x \\"), // a shebang line that goes on
+            ("(a)\n; c\n(b)", 5..5, "#|"),  // a block comment in a line comment
+            ("\r\n,;[]\r;\r", 9..9, "11"),  // `,;` was a symbol, `;[]` is now a comment
+            ("(a)\r\n(b)\r(c)", 5..5, ";"), // a comment ends at any line break
+        ] {
+            let mut doc = Document::new(text);
+            doc.edit(range, replacement);
+            assert_reparsed(&doc);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut doc = Document::new("(a) (b) (c)");
+        doc.edit(6..7, "");
+        assert_eq!(doc.errors().len(), 1);
+        assert_reparsed(&doc);
+        // reparsing all of the text still finds what changed
+        let change = doc.edit(6..6, ")");
+        assert!(doc.errors().is_empty());
+        assert_eq!(
+            change,
+            Change {
+                old: 1..1,
+                new: 1..3
+            }
+        );
+        assert_reparsed(&doc);
+    }
+
+    #[test]
+    fn test_forms_share_the_source() {
+        let mut doc = Document::new("(define x 1)\n(define y 2)\n(f x y)\n");
+        doc.edit(31..32, "z");
+        assert!(in_source(&doc));
+        doc.edit(23..24, "20");
+        assert!(in_source(&doc));
+        doc.edit(0..0, "'a ");
+        assert!(in_source(&doc));
+        // with an error, all of the text is read again
+        doc.edit(3..3, "(");
+        assert!(!doc.errors().is_empty());
+        assert!(in_source(&doc));
+        doc.edit(3..4, "");
+        assert!(in_source(&doc));
+    }
+
+    #[test]
+    fn test_random_edits() {
+        let pieces = [
+            "(", ")", "[", "]", " ", "\n", "a", "1", "'", "`", ",", "#;", "\"", ";", "#|", "|#",
+            "|", "\\", "#\\", ".", "#(", "λ", "\r", "\r\n", "\u{feff}",
+        ];
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        let mut doc = Document::new("(define (f x)\n  (+ x 1))\n\n(f 2) 'a \"s\" ; c\n#(1 2)\n");
+        for _ in 0..3000 {
+            let text = doc.text();
+            let mut bounds: Vec<_> = (0..=text.len())
+                .filter(|i| text.is_char_boundary(*i))
+                .collect();
+            let start = bounds[rng.below(bounds.len())];
+            bounds.retain(|i| *i >= start && *i <= start + 4);
+            let end = bounds[rng.below(bounds.len())];
+            doc.edit(start..end, &rng.input(&pieces, 3));
+            assert_reparsed(&doc);
+            if doc.text().len() > 200 {
+                doc = Document::new("(a) (b)\n(c)");
+            }
+        }
+    }
+}
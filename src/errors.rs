@@ -5,12 +5,81 @@ pub enum ParsingError {
     /// Invalid Syntax error. The first argument is the position, the second is the token that
     /// triggered the error. And the optional string is an optional error message.
     InvalidSyntax(TokInfo, String, Option<String>),
-    /// Unexpected token error. The first argument is the error produced by pest
-    Pest(String),
+    /// Unexpected token error. The first argument is the position of the unexpected token, the
+    /// second are the kinds of tokens that were expected there, and the third is the token found,
+    /// which is empty at the end of the input.
+    UnexpectedToken(TokInfo, Vec<Expected>, String),
+    /// Unbalanced bracket error, for a bracket that is closed by the wrong kind of bracket, is
+    /// never closed, or is closed without having been opened.
+    UnbalancedBracket(Box<BracketError>),
     /// Nothing to parse error. This can be handled as not an erorr, but as a warning.
     NothingToParse,
     /// Bad wellformed config error.
     BadWellformedConfig,
+    /// The input couldn't be read, when reading it from a stream.
+    Io(std::io::Error),
+    /// A file saved by DrRacket in the WXME format couldn't be decoded, see `wxme::decode`.
+    InvalidWxme(String),
+    /// A form or a block comment is nested deeper than `ReaderOptions::max_depth`, or deeper
+    /// than the grammar has stack for. The argument is the first token of the first form that
+    /// is too deep.
+    TooDeep(TokInfo),
+    /// The input has more forms than `ReaderOptions::max_forms`. The argument is the first form
+    /// past the limit.
+    TooManyForms(TokInfo),
+    /// The input is longer than `ReaderOptions::max_size`. The argument is its length in bytes.
+    TooLarge(usize),
+}
+
+/// The kinds of tokens that can be expected where an `UnexpectedToken` error happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Expected {
+    Expression,
+    Dot,
+    Symbol,
+    Keyword,
+    Number,
+    Boolean,
+    Character,
+    String,
+    StringContent, // more of a string that hasn't been closed yet
+    Escape,
+    HereStringTerminator,
+    OpeningBracket,
+    EndOfInput,
+}
+
+impl std::fmt::Display for Expected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expected::Expression => write!(f, "an expression"),
+            Expected::Dot => write!(f, "a `.`"),
+            Expected::Symbol => write!(f, "a symbol"),
+            Expected::Keyword => write!(f, "a keyword"),
+            Expected::Number => write!(f, "a number"),
+            Expected::Boolean => write!(f, "a boolean"),
+            Expected::Character => write!(f, "a character"),
+            Expected::String => write!(f, "a string"),
+            Expected::StringContent => write!(f, "the rest of the string and its closing `\"`"),
+            Expected::Escape => write!(f, "a valid escape"),
+            Expected::HereStringTerminator => write!(f, "the terminator of the here string"),
+            Expected::OpeningBracket => write!(f, "an opening bracket"),
+            Expected::EndOfInput => write!(f, "the end of the input"),
+        }
+    }
+}
+
+/// The brackets at fault in an `UnbalancedBracket` error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BracketError {
+    /// The opening bracket at fault, if there is one.
+    pub opener: Option<TokInfo>,
+    /// The closing bracket the opener needs.
+    pub expected: Option<char>,
+    /// The closing bracket at fault, which is `None` when the opener is never closed.
+    pub closer: Option<TokInfo>,
+    /// Where the missing closer most likely goes, when the indentation suggests a place.
+    pub missing_at: Option<TokInfo>,
 }
 
 impl std::error::Error for ParsingError {}
@@ -25,9 +94,91 @@ impl std::fmt::Display for ParsingError {
                 }
                 Ok(())
             }
-            ParsingError::Pest(msg) => write!(f, "Pest error:\n {}", msg),
+            ParsingError::UnexpectedToken(pos, expected, found) => {
+                if found.is_empty() {
+                    write!(f, "Unexpected end of input")?;
+                } else {
+                    write!(f, "Unexpected `{}`", found)?;
+                }
+                let (line, col) = pos.start_line_col();
+                write!(f, " at line {}, column {}", line, col)?;
+                for (i, kind) in expected.iter().enumerate() {
+                    match i {
+                        0 => write!(f, ", expected {}", kind)?,
+                        _ if i + 1 == expected.len() => write!(f, " or {}", kind)?,
+                        _ => write!(f, ", {}", kind)?,
+                    }
+                }
+                Ok(())
+            }
+            ParsingError::UnbalancedBracket(e) => {
+                let BracketError {
+                    opener,
+                    expected,
+                    closer,
+                    missing_at,
+                } = e.as_ref();
+                let at = |tok: &TokInfo| {
+                    let (line, col) = tok.start_line_col();
+                    format!("line {}, column {}", line, col)
+                };
+                match (opener, expected, closer) {
+                    (Some(opener), Some(expected), Some(closer)) => write!(
+                        f,
+                        "Mismatched bracket: `{}` at {} is closed by `{}` at {}, expected `{}`",
+                        opener.as_str(),
+                        at(opener),
+                        closer.as_str(),
+                        at(closer),
+                        expected
+                    )?,
+                    (Some(opener), Some(expected), None) => write!(
+                        f,
+                        "Unclosed bracket: `{}` at {} is never closed, expected `{}`",
+                        opener.as_str(),
+                        at(opener),
+                        expected
+                    )?,
+                    (_, _, Some(closer)) => write!(
+                        f,
+                        "Unexpected bracket: `{}` at {} closes nothing",
+                        closer.as_str(),
+                        at(closer)
+                    )?,
+                    _ => write!(f, "Unbalanced bracket")?,
+                }
+                if let Some(missing) = missing_at {
+                    write!(
+                        f,
+                        "\nThe closing bracket is probably missing at {}",
+                        at(missing)
+                    )?;
+                }
+                Ok(())
+            }
             ParsingError::NothingToParse => write!(f, "Nothing to parse"),
             ParsingError::BadWellformedConfig => write!(f, "Bad wellformed config"),
+            ParsingError::Io(e) => write!(f, "Could not read the input: {}", e),
+            ParsingError::InvalidWxme(msg) => write!(f, "Could not decode the WXME file: {}", msg),
+            ParsingError::TooDeep(pos) => {
+                let (line, col) = pos.start_line_col();
+                write!(
+                    f,
+                    "The form at line {}, column {} is nested too deeply to be read",
+                    line, col
+                )
+            }
+            ParsingError::TooManyForms(pos) => {
+                let (line, col) = pos.start_line_col();
+                write!(
+                    f,
+                    "Too many forms to read, gave up at line {}, column {}",
+                    line, col
+                )
+            }
+            ParsingError::TooLarge(len) => {
+                write!(f, "The input is too large to read, with {} bytes", len)
+            }
         }
     }
 }
@@ -0,0 +1,1239 @@
+use std::sync::Arc;
+
+use pest::unicode::ALPHABETIC;
+
+use crate::{
+    depth::check_depth,
+    errors::ParsingError,
+    sexpr::{Atom, RegexpKind, Sexpr, Source, TokInfo},
+    sexpr_parser::{bom_len, unexpected_token, ReaderOptions, Rule, SexprParser, StringPart},
+};
+
+/// A hand-written reader, which reads the same as `SexprParser` but much faster, as it doesn't
+/// build a tree of pest pairs first. It follows the grammar rule by rule, so that it matches the
+/// same text, and turns what it matches into forms with the same functions as `SexprParser`, so
+/// that the options and the errors of the forms are the same. When the grammar can't read the
+/// input, the input is read again keeping track of the rules that fail and where, like pest
+/// does, to give the same `UnexpectedToken` error.
+pub struct FastParser;
+
+impl FastParser {
+    pub fn parse_all(input: &str) -> Result<Vec<Sexpr<TokInfo>>, ParsingError> {
+        Self::parse_all_with(input, &ReaderOptions::default())
+    }
+
+    pub fn parse_all_with(
+        input: &str,
+        opts: &ReaderOptions,
+    ) -> Result<Vec<Sexpr<TokInfo>>, ParsingError> {
+        opts.check_size(input.len())?;
+        let source = Arc::new(Source::new(input));
+        let start = bom_len(&source, 0, input.len());
+        // like for the grammar, the forms are only read once they aren't nested too deep
+        check_depth(&source, start, input.len(), Rule::program, opts)?;
+        Reader::new(&source, start, input.len(), opts).program()
+    }
+}
+
+/// What a form reads as, which can be an error found once the grammar matched it, like a
+/// misplaced dot, along with the offset where the form ends.
+type Form = (Result<Sexpr<TokInfo>, ParsingError>, usize);
+
+/// Like `Form`, for the atoms.
+type AtomForm = (Result<Atom<TokInfo>, ParsingError>, usize);
+
+/// The parts of a complex number, with where each one starts and ends, and whether it's
+/// imaginary.
+type Parts = Vec<(usize, usize, bool)>;
+
+/// A rule of the grammar that is atomic, so that none of the rules in it are tracked. It gives
+/// back where it ends when it matches at the given offset.
+type Matcher<'a> = fn(&Reader<'a>, usize) -> Option<usize>;
+
+/// The prefixes of the forms, like `'` in `'x`, with the rules that read them, in the order the
+/// grammar tries them.
+const PREFIXES: [(Rule, &str); 10] = [
+    (Rule::quoted, "'"),
+    (Rule::quasiquoted, "`"),
+    (Rule::unquote_spliced, ",@"),
+    (Rule::unquoted, ","),
+    (Rule::syntax_quoted, "#'"),
+    (Rule::quasisyntax_quoted, "#`"),
+    (Rule::unsyntax_spliced, "#,@"),
+    (Rule::unsyntaxed, "#,"),
+    (Rule::boxed, "#&"),
+    (Rule::sexpr_comment, "#;"),
+];
+
+const CHAR_NAMES: [&str; 12] = [
+    "null",
+    "nul",
+    "backspace",
+    "tab",
+    "newline",
+    "linefeed",
+    "vtab",
+    "page",
+    "return",
+    "space",
+    "rubout",
+    "delete",
+];
+
+/// The rules of the numbers written in a radix, which are repeated for every radix.
+struct Radix {
+    radix: u32,
+    prefix: (Rule, &'static str),
+    real: Rule,
+    imag: Rule,
+    complex: Rule,
+}
+
+/// The radixes of the numbers, in the order the grammar tries them.
+const RADIXES: [Radix; 4] = [
+    Radix {
+        radix: 2,
+        prefix: (Rule::radix2, "#b"),
+        real: Rule::real2,
+        imag: Rule::imag2,
+        complex: Rule::complex2,
+    },
+    Radix {
+        radix: 8,
+        prefix: (Rule::radix8, "#o"),
+        real: Rule::real8,
+        imag: Rule::imag8,
+        complex: Rule::complex8,
+    },
+    Radix {
+        radix: 16,
+        prefix: (Rule::radix16, "#x"),
+        real: Rule::real16,
+        imag: Rule::imag16,
+        complex: Rule::complex16,
+    },
+    Radix {
+        radix: 10,
+        prefix: (Rule::radix10, "#d"),
+        real: Rule::real10,
+        imag: Rule::imag10,
+        complex: Rule::complex10,
+    },
+];
+
+/// The elements of a list or vector, with the dots in between them like `Elements`, and the
+/// first error of the elements that can't be read.
+#[derive(Default)]
+struct Items {
+    forms: Vec<Sexpr<TokInfo>>,
+    dots: Vec<(usize, TokInfo)>,
+    error: Option<ParsingError>,
+}
+
+/// Reads `source.text()[start..end]` by following the rules of `sexpr.pest`. Every rule is a
+/// function that gives back where it ends when it matches, and `None` when it doesn't, so that
+/// the next alternative is tried like in the grammar. The offsets are the ones of the text.
+struct Reader<'a> {
+    source: &'a Arc<Source>,
+    text: &'a str, // the text of the source, up to the end of the input
+    bytes: &'a [u8],
+    start: usize,
+    opts: &'a ReaderOptions,
+    forms: usize, // how many forms were read, counting the ones that start before this one
+    too_many: Option<TokInfo>,
+    // the rules that failed at the furthest offset that a rule failed at, kept like pest does
+    // when `tracking`, see `track`
+    tracking: bool,
+    attempt_pos: usize,
+    attempts: Vec<Rule>,
+}
+
+impl<'a> Reader<'a> {
+    fn new(source: &'a Arc<Source>, start: usize, end: usize, opts: &'a ReaderOptions) -> Self {
+        let text = &source.text()[..end];
+        Reader {
+            source,
+            text,
+            bytes: text.as_bytes(),
+            start,
+            opts,
+            forms: 0,
+            too_many: None,
+            tracking: false,
+            attempt_pos: start,
+            attempts: Vec::new(),
+        }
+    }
+
+    fn tok(&self, start: usize, end: usize) -> TokInfo {
+        TokInfo::new(self.source.clone(), start, end)
+    }
+
+    /// Reads every form of the input, giving the first error like `SexprParser` does, which is
+    /// the error of the grammar, then the forms past `max_forms`, and the forms that can't be
+    /// read. The forms nested too deep were found before reading, see `check_depth`.
+    fn program(mut self) -> Result<Vec<Sexpr<TokInfo>>, ParsingError> {
+        let Some(forms) = self.read_program() else {
+            return Err(self.grammar_error(Self::read_program));
+        };
+        if let Some(tok) = self.too_many.take() {
+            return Err(ParsingError::TooManyForms(tok));
+        }
+        forms
+            .into_iter()
+            .filter(|form| !matches!(form, Err(ParsingError::NothingToParse)))
+            .collect()
+    }
+
+    /// Reads the input again with `read`, which failed, to find the error pest gives for it.
+    fn grammar_error<T>(&mut self, read: fn(&mut Self) -> Option<T>) -> ParsingError {
+        self.tracking = true;
+        self.attempt_pos = self.start;
+        self.attempts.clear();
+        self.forms = 0;
+        read(self);
+        let (start, end) = (self.start, self.text.len());
+        let rules = std::mem::take(&mut self.attempts);
+        unexpected_token(self.source, start, end, self.attempt_pos, rules)
+    }
+
+    /// Matches a rule of the grammar at `pos` with `read`, keeping track of it when it fails.
+    fn rule<T>(
+        &mut self,
+        rule: Rule,
+        pos: usize,
+        read: impl FnOnce(&mut Self) -> Option<T>,
+    ) -> Option<T> {
+        if !self.tracking {
+            return read(self);
+        }
+        let attempts = self.attempts_at(pos);
+        let matched = read(self);
+        if matched.is_none() {
+            self.track(rule, pos, attempts);
+        }
+        matched
+    }
+
+    fn attempts_at(&self, pos: usize) -> usize {
+        match pos == self.attempt_pos {
+            true => self.attempts.len(),
+            false => 0,
+        }
+    }
+
+    /// Keeps track of a rule that failed at `pos`, like pest does, which reports the rules that
+    /// failed at the furthest offset. The rules in a rule that failed are replaced by it, unless
+    /// only one of them failed there. `attempts` is how many rules had failed at `pos` before.
+    fn track(&mut self, rule: Rule, pos: usize, attempts: usize) {
+        if self.attempts_at(pos) == attempts + 1 {
+            return;
+        }
+        if pos == self.attempt_pos {
+            self.attempts.truncate(attempts);
+        }
+        if pos > self.attempt_pos {
+            self.attempts.clear();
+            self.attempt_pos = pos;
+        }
+        if pos == self.attempt_pos {
+            self.attempts.push(rule);
+        }
+    }
+
+    /// Matches the first of the given rules that matches at `pos`, giving back which one it is
+    /// and where it ends.
+    fn first_of(&mut self, pos: usize, rules: &[(Rule, Matcher<'a>)]) -> Option<(Rule, usize)> {
+        rules.iter().find_map(|&(rule, matcher)| {
+            let end = self.rule(rule, pos, |r| matcher(r, pos))?;
+            Some((rule, end))
+        })
+    }
+
+    /// Goes back to the given count of forms, as the forms past it weren't matched after all.
+    fn rollback(&mut self, forms: usize) {
+        self.forms = forms;
+        if forms <= self.opts.max_forms {
+            self.too_many = None;
+        }
+    }
+
+    /// `program = { SOI ~ sexpr* ~ EOI }`
+    fn read_program(&mut self) -> Option<Vec<Result<Sexpr<TokInfo>, ParsingError>>> {
+        let start = self.start;
+        self.rule(Rule::program, start, |r| {
+            let mut forms = Vec::new();
+            let mut pos = r.skip(start);
+            while let Some((form, end)) = r.sexpr(pos) {
+                forms.push(form);
+                pos = r.skip(end);
+            }
+            r.eoi(pos)?;
+            Some(forms)
+        })
+    }
+
+    /// `sexpr = { vector | hash | prefab | atom | slist }`
+    fn sexpr(&mut self, pos: usize) -> Option<Form> {
+        let index = self.forms;
+        let read = self.rule(Rule::sexpr, pos, |r| {
+            let alternatives: [fn(&mut Self, usize) -> Option<Form>; 5] = [
+                Self::vector,
+                Self::hash,
+                Self::prefab,
+                Self::atom,
+                Self::slist,
+            ];
+            for alternative in alternatives {
+                r.rollback(index + 1);
+                if let Some(form) = alternative(r, pos) {
+                    return Some(form);
+                }
+            }
+            None
+        });
+        match &read {
+            Some((_, end)) if index == self.opts.max_forms => {
+                self.too_many = Some(self.tok(pos, *end));
+            }
+            Some(_) => {}
+            None => self.rollback(index),
+        }
+        read
+    }
+
+    /// `slist = { "(" ~ (dot | sexpr)* ~ ")" | ... }`, with the other brackets
+    fn slist(&mut self, pos: usize) -> Option<Form> {
+        self.rule(Rule::slist, pos, |r| {
+            let (items, end) = r.items(pos)?;
+            let tok = r.tok(pos, end);
+            let list = r.build(pos, items, |forms, dots| {
+                SexprParser::build_list(forms, dots, tok)
+            });
+            Some((list, end))
+        })
+    }
+
+    /// `vector = ${ "#" ~ vector_len? ~ vector_items }`
+    fn vector(&mut self, pos: usize) -> Option<Form> {
+        self.rule(Rule::vector, pos, |r| {
+            r.literal(pos, "#")?;
+            let len_end = r.rule(Rule::vector_len, pos + 1, |r| {
+                r.repeat(pos + 1, 1, usize::MAX, |b| b.is_ascii_digit())
+            });
+            let open = len_end.unwrap_or(pos + 1);
+            let len = len_end.map(|end| r.text[pos + 1..end].parse::<usize>().ok());
+            let (items, end) = r.vector_items(open)?;
+            let tok = r.tok(pos, end);
+            let vector = r.build(open, items, |forms, dots| {
+                SexprParser::build_vector(forms, dots, len, tok)
+            });
+            Some((vector, end))
+        })
+    }
+
+    /// `hash = ${ hash_kind ~ vector_items }`
+    fn hash(&mut self, pos: usize) -> Option<Form> {
+        self.rule(Rule::hash, pos, |r| {
+            let open = r.rule(Rule::hash_kind, pos, |r| {
+                ["#hasheqv", "#hasheq", "#hashalw", "#hash"]
+                    .iter()
+                    .find_map(|kind| r.literal(pos, kind))
+            })?;
+            let kind = SexprParser::hash_kind(&r.text[pos..open]);
+            let (items, end) = r.vector_items(open)?;
+            let tok = r.tok(pos, end);
+            let hash = r.build(open, items, |forms, dots| {
+                SexprParser::build_hash(kind, forms, dots, tok)
+            });
+            Some((hash, end))
+        })
+    }
+
+    /// `prefab = ${ "#s" ~ vector_items }`
+    fn prefab(&mut self, pos: usize) -> Option<Form> {
+        self.rule(Rule::prefab, pos, |r| {
+            let open = r.literal(pos, "#s")?;
+            let (items, end) = r.vector_items(open)?;
+            let tok = r.tok(pos, end);
+            let prefab = r.build(open, items, |forms, dots| {
+                SexprParser::build_prefab(forms, dots, tok)
+            });
+            Some((prefab, end))
+        })
+    }
+
+    /// `vector_items = !{ "(" ~ (dot | sexpr)* ~ ")" | ... }`
+    fn vector_items(&mut self, pos: usize) -> Option<(Items, usize)> {
+        self.rule(Rule::vector_items, pos, |r| r.items(pos))
+    }
+
+    /// Reads the elements in between the brackets at `pos`. The sexpr comments are left out,
+    /// like in `parse_elements`.
+    fn items(&mut self, pos: usize) -> Option<(Items, usize)> {
+        let close = match self.bytes.get(pos) {
+            Some(b'(') => b')',
+            Some(b'[') => b']',
+            Some(b'{') => b'}',
+            _ => return None,
+        };
+        let mut items = Items::default();
+        let mut pos = self.skip(pos + 1);
+        loop {
+            if let Some(end) = self.dot(pos) {
+                items.dots.push((items.forms.len(), self.tok(pos, end)));
+                pos = self.skip(end);
+                continue;
+            }
+            let Some((form, end)) = self.sexpr(pos) else {
+                break;
+            };
+            if !self.text[pos..].starts_with("#;") {
+                match form {
+                    Ok(form) => items.forms.push(form),
+                    Err(e) => {
+                        items.error.get_or_insert(e);
+                    }
+                }
+            }
+            pos = self.skip(end);
+        }
+        (self.bytes.get(pos) == Some(&close)).then_some((items, pos + 1))
+    }
+
+    /// Builds a list or vector out of its items with `build`, unless its opening bracket at
+    /// `open` isn't allowed or one of the items can't be read.
+    fn build(
+        &self,
+        open: usize,
+        items: Items,
+        build: impl FnOnce(
+            Vec<Sexpr<TokInfo>>,
+            Vec<(usize, TokInfo)>,
+        ) -> Result<Sexpr<TokInfo>, ParsingError>,
+    ) -> Result<Sexpr<TokInfo>, ParsingError> {
+        SexprParser::check_bracket(self.tok(open, open + 1), self.opts)?;
+        match items.error {
+            Some(e) => Err(e),
+            None => build(items.forms, items.dots),
+        }
+    }
+
+    /// `dot = @{ "." ~ &(delimiter | "#|" | "#;") }`
+    fn dot(&mut self, pos: usize) -> Option<usize> {
+        self.rule(Rule::dot, pos, |r| {
+            let end = r.literal(pos, ".")?;
+            let delimited = r.is_delimiter(end)
+                || r.literal(end, "#|").is_some()
+                || r.literal(end, "#;").is_some();
+            delimited.then_some(end)
+        })
+    }
+
+    /// `atom = { bool | character | ... | symbol }`, which are the forms that aren't lists. The
+    /// forms behind a prefix like `'` are atoms too.
+    fn atom(&mut self, pos: usize) -> Option<Form> {
+        let (atom, end) = self.rule(Rule::atom, pos, |r| {
+            let atoms: [fn(&mut Self, usize) -> Option<AtomForm>; 8] = [
+                Self::boolean,
+                Self::character,
+                Self::number,
+                Self::legacy_number,
+                Self::string,
+                Self::byte_string,
+                Self::regexp,
+                Self::here_string,
+            ];
+            let read = (atoms.iter().find_map(|read| read(r, pos)))
+                .or_else(|| {
+                    (PREFIXES.iter()).find_map(|&(rule, prefix)| r.abbreviation(rule, prefix, pos))
+                })
+                .or_else(|| r.keyword(pos))
+                .or_else(|| r.symbol(pos))?;
+            Some(read)
+        })?;
+        Some((atom.map(|atom| Sexpr::Atom(atom, self.tok(pos, end))), end))
+    }
+
+    /// Reads the form after a prefix like `'`, with the rule of the prefix, like
+    /// `quoted = { "'" ~ sexpr }`.
+    fn abbreviation(&mut self, rule: Rule, prefix: &str, pos: usize) -> Option<AtomForm> {
+        self.rule(rule, pos, |r| {
+            let start = r.literal(pos, prefix)?;
+            let inner = r.skip(start);
+            let (form, end) = r.sexpr(inner)?;
+            let atom = match rule {
+                Rule::sexpr_comment => Err(ParsingError::NothingToParse),
+                _ => form.map(|form| SexprParser::abbreviation(rule, form, r.tok(inner, end))),
+            };
+            Some((atom, end))
+        })
+    }
+
+    /// `bool = { bool_false | bool_true }`
+    fn boolean(&mut self, pos: usize) -> Option<AtomForm> {
+        let end = self.rule(Rule::bool, pos, |r| {
+            (r.rule(Rule::bool_false, pos, |r| {
+                r.literal(pos, "#false").or_else(|| r.literal(pos, "#f"))
+            }))
+            .or_else(|| {
+                r.rule(Rule::bool_true, pos, |r| {
+                    r.literal(pos, "#true").or_else(|| r.literal(pos, "#t"))
+                })
+            })
+        })?;
+        Some((SexprParser::boolean(self.tok(pos, end), self.opts), end))
+    }
+
+    /// `character = ${ "#\\" ~ (char_named | char_octal | char_unicode | char_any) }`
+    fn character(&mut self, pos: usize) -> Option<AtomForm> {
+        self.rule(Rule::character, pos, |r| {
+            let start = r.literal(pos, "#\\")?;
+            let (rule, end) = r.first_of(
+                start,
+                &[
+                    (Rule::char_named, Self::char_named),
+                    (Rule::char_octal, |r, pos| {
+                        r.repeat(pos, 3, 3, |b| matches!(b, b'0'..=b'7'))
+                    }),
+                    (Rule::char_unicode, Self::unicode),
+                    (Rule::char_any, Self::char_any),
+                ],
+            )?;
+            let tok = r.tok(pos, end);
+            Some((SexprParser::character(rule, &r.text[start..end], tok), end))
+        })
+    }
+
+    /// `char_named = @{ (^"null" | ...) ~ !ALPHABETIC }`
+    fn char_named(&self, pos: usize) -> Option<usize> {
+        let name = CHAR_NAMES
+            .iter()
+            .find(|name| self.insensitive(pos, name).is_some())?;
+        let end = pos + name.len();
+        (!self.is_alphabetic(end)).then_some(end)
+    }
+
+    /// `char_any = @{ !ALPHABETIC ~ ANY | ALPHABETIC ~ !ALPHABETIC }`
+    fn char_any(&self, pos: usize) -> Option<usize> {
+        let end = self.any(pos)?;
+        (!self.is_alphabetic(pos) || !self.is_alphabetic(end)).then_some(end)
+    }
+
+    /// `number = ${ (... ~ complex2 | ... ~ complex8 | ... ~ complex16 | ... ~ complex10) ~
+    /// &delimiter }`, where each radix has its prefix and an optional exactness, in any order
+    fn number(&mut self, pos: usize) -> Option<AtomForm> {
+        self.rule(Rule::number, pos, |r| {
+            let (radix, exactness, parts, end) =
+                RADIXES.iter().find_map(|radix| r.number_in(radix, pos))?;
+            r.delimiter(end)?;
+            let parts: Vec<_> = (parts.iter())
+                .map(|&(start, end, is_imag)| (&r.text[start..end], is_imag))
+                .collect();
+            let exactness = exactness.map(|start| &r.text[start..start + 2]);
+            let tok = r.tok(pos, end);
+            let number = SexprParser::number(&parts, radix.radix, exactness, tok, r.opts);
+            Some((number, end))
+        })
+    }
+
+    /// Reads the prefixes and the complex number of a number in the given radix, giving back
+    /// where the exactness is, if it has one, and the parts of the complex number.
+    fn number_in<'r>(
+        &mut self,
+        radix: &'r Radix,
+        pos: usize,
+    ) -> Option<(&'r Radix, Option<usize>, Parts, usize)> {
+        let (rule, prefix) = radix.prefix;
+        let radix_at = |r: &mut Self, pos| r.rule(rule, pos, |r| r.insensitive(pos, prefix));
+        let exactness_at = |r: &mut Self, pos| {
+            r.rule(Rule::exactness, pos, |r| {
+                r.insensitive(pos, "#e")
+                    .or_else(|| r.insensitive(pos, "#i"))
+            })
+        };
+        let (exactness, start) = if let Some(end) = radix_at(self, pos) {
+            let exact = exactness_at(self, end);
+            (exact.map(|_| end), exact.unwrap_or(end))
+        } else if let Some(end) = exactness_at(self, pos) {
+            match radix_at(self, end) {
+                Some(start) => (Some(pos), start),
+                // the radix is optional after an exactness only in radix 10
+                None if radix.radix == 10 => (Some(pos), end),
+                None => return None,
+            }
+        } else if radix.radix == 10 {
+            (None, pos)
+        } else {
+            return None;
+        };
+        let (parts, end) = self.complex(radix, start)?;
+        Some((radix, exactness, parts, end))
+    }
+
+    /// `complex10 = ${ real10 ~ "@" ~ real10 | real10 ~ imag10 | imag10 | real10 }`, with the
+    /// offsets of the parts and whether they're imaginary
+    fn complex(&mut self, radix: &Radix, pos: usize) -> Option<(Parts, usize)> {
+        let real = |r: &mut Self, pos| r.rule(radix.real, pos, |r| r.real(radix.radix, pos));
+        let imag = |r: &mut Self, pos| r.rule(radix.imag, pos, |r| r.imag(radix.radix, pos));
+        self.rule(radix.complex, pos, |r| {
+            if let Some(mid) = real(r, pos).and_then(|end| r.literal(end, "@")) {
+                if let Some(end) = real(r, mid) {
+                    return Some((vec![(pos, mid - 1, false), (mid, end, false)], end));
+                }
+            }
+            if let Some(mid) = real(r, pos) {
+                if let Some(end) = imag(r, mid) {
+                    return Some((vec![(pos, mid, false), (mid, end, true)], end));
+                }
+            }
+            if let Some(end) = imag(r, pos) {
+                return Some((vec![(pos, end, true)], end));
+            }
+            let end = real(r, pos)?;
+            Some((vec![(pos, end, false)], end))
+        })
+    }
+
+    /// `real10 = @{ inf_nan | sign? ~ ureal10 }`
+    fn real(&self, radix: u32, pos: usize) -> Option<usize> {
+        self.inf_nan(pos)
+            .or_else(|| self.ureal(radix, self.sign(pos).unwrap_or(pos)))
+    }
+
+    /// `imag10 = @{ (inf_nan | sign ~ ureal10?) ~ ^"i" }`
+    fn imag(&self, radix: u32, pos: usize) -> Option<usize> {
+        let end = match self.inf_nan(pos) {
+            Some(end) => end,
+            None => {
+                let start = self.sign(pos)?;
+                self.ureal(radix, start).unwrap_or(start)
+            }
+        };
+        self.insensitive(end, "i")
+    }
+
+    /// `inf_nan = _{ sign ~ (^"inf" | ^"nan") ~ "." ~ ("0" | ^"f") }`
+    fn inf_nan(&self, pos: usize) -> Option<usize> {
+        let start = self.sign(pos)?;
+        let dot = (self.insensitive(start, "inf")).or_else(|| self.insensitive(start, "nan"))?;
+        let end = self.literal(dot, ".")?;
+        self.literal(end, "0")
+            .or_else(|| self.insensitive(end, "f"))
+    }
+
+    fn sign(&self, pos: usize) -> Option<usize> {
+        matches!(self.bytes.get(pos), Some(b'+' | b'-')).then_some(pos + 1)
+    }
+
+    /// `ureal10 = _{ decimal10 | uinteger10 ~ "/" ~ uinteger10 | uinteger10 }`
+    fn ureal(&self, radix: u32, pos: usize) -> Option<usize> {
+        if let Some(end) = self.decimal(radix, pos) {
+            return Some(end);
+        }
+        let end = self.uinteger(radix, pos)?;
+        let denominator = self.literal(end, "/");
+        Some(
+            denominator
+                .and_then(|start| self.uinteger(radix, start))
+                .unwrap_or(end),
+        )
+    }
+
+    /// `uinteger10 = _{ digit10+ ~ "#"* }`
+    fn uinteger(&self, radix: u32, pos: usize) -> Option<usize> {
+        let end = self.digits(radix, pos)?;
+        Some(self.hashes(end))
+    }
+
+    /// `decimal10 = _{ digit10+ ~ "." ~ digit10* ~ "#"* ~ exp10? | "." ~ digit10+ ~ "#"* ~
+    /// exp10? | digit10+ ~ "#"+ ~ "." ~ "#"* ~ exp10? | uinteger10 ~ exp10 }`
+    fn decimal(&self, radix: u32, pos: usize) -> Option<usize> {
+        let with_exp = |end| self.exp(radix, end).unwrap_or(end);
+        let Some(digits) = self.digits(radix, pos) else {
+            let start = self.literal(pos, ".")?;
+            let end = self.digits(radix, start)?;
+            return Some(with_exp(self.hashes(end)));
+        };
+        if let Some(start) = self.literal(digits, ".") {
+            let end = self.digits(radix, start).unwrap_or(start);
+            return Some(with_exp(self.hashes(end)));
+        }
+        let hashes = self.hashes(digits);
+        if hashes > digits {
+            if let Some(start) = self.literal(hashes, ".") {
+                return Some(with_exp(self.hashes(start)));
+            }
+        }
+        self.exp(radix, hashes)
+    }
+
+    /// `exp10 = _{ exp_mark10 ~ sign? ~ digit10+ }`, where radix 16 only has the exponent
+    /// markers that aren't digits
+    fn exp(&self, radix: u32, pos: usize) -> Option<usize> {
+        let marks: &[u8] = if radix == 16 { b"sl" } else { b"sldef" };
+        let mark = self.bytes.get(pos)?.to_ascii_lowercase();
+        if !marks.contains(&mark) {
+            return None;
+        }
+        self.digits(radix, self.sign(pos + 1).unwrap_or(pos + 1))
+    }
+
+    fn digits(&self, radix: u32, pos: usize) -> Option<usize> {
+        self.repeat(pos, 1, usize::MAX, |b| char::from(b).is_digit(radix))
+    }
+
+    fn hashes(&self, pos: usize) -> usize {
+        self.repeat(pos, 0, usize::MAX, |b| b == b'#')
+            .unwrap_or(pos)
+    }
+
+    /// `legacy_number = ${ (legacy_hex | legacy_float | legacy_integer) ~ &delimiter }`
+    fn legacy_number(&mut self, pos: usize) -> Option<AtomForm> {
+        self.rule(Rule::legacy_number, pos, |r| {
+            let (rule, end) = r.first_of(
+                pos,
+                &[
+                    (Rule::legacy_hex, |r, pos| {
+                        let start = r.literal(pos, "0x")?;
+                        r.repeat(start, 1, usize::MAX, |b| b.is_ascii_hexdigit())
+                    }),
+                    (Rule::legacy_float, Self::legacy_float),
+                    (Rule::legacy_integer, |r, pos| {
+                        r.legacy_digits(r.literal(pos, "-").unwrap_or(pos))
+                    }),
+                ],
+            )?;
+            r.delimiter(end)?;
+            let tok = r.tok(pos, end);
+            Some((SexprParser::legacy_number(rule, tok, r.opts), end))
+        })
+    }
+
+    /// `legacy_float = @{ "-"? ~ (legacy_digits ~ ("." ~ legacy_digits? | "f") | "." ~
+    /// legacy_digits) }`
+    fn legacy_float(&self, pos: usize) -> Option<usize> {
+        let start = self.literal(pos, "-").unwrap_or(pos);
+        match self.legacy_digits(start) {
+            Some(end) => match self.literal(end, ".") {
+                Some(fraction) => Some(self.legacy_digits(fraction).unwrap_or(fraction)),
+                None => self.literal(end, "f"),
+            },
+            None => self.legacy_digits(self.literal(start, ".")?),
+        }
+    }
+
+    /// `legacy_digits = _{ ASCII_DIGIT ~ (ASCII_DIGIT | "_")* }`
+    fn legacy_digits(&self, pos: usize) -> Option<usize> {
+        self.repeat(pos, 1, 1, |b| b.is_ascii_digit())?;
+        self.repeat(pos, 1, usize::MAX, |b| b.is_ascii_digit() || b == b'_')
+    }
+
+    /// `&delimiter`, where the end of the input is the only rule that can fail
+    fn delimiter(&mut self, pos: usize) -> Option<()> {
+        match self.is_delimiter(pos) && pos < self.bytes.len() {
+            true => Some(()),
+            false => self.eoi(pos),
+        }
+    }
+
+    /// Checks if the byte at `pos` is a `delimiter`, or the end of the input.
+    fn is_delimiter(&self, pos: usize) -> bool {
+        self.bytes
+            .get(pos)
+            .is_none_or(|b| b" \n\r([{)]}\";'`,".contains(b))
+    }
+
+    fn eoi(&mut self, pos: usize) -> Option<()> {
+        self.rule(Rule::EOI, pos, |r| (pos == r.bytes.len()).then_some(()))
+    }
+
+    /// `string = ${ "\"" ~ (raw_string | escape)* ~ "\"" }`
+    fn string(&mut self, pos: usize) -> Option<AtomForm> {
+        let (parts, end) = self.string_parts(Rule::string, pos)?;
+        let string = SexprParser::decode_string(parts, self.opts, self.source);
+        Some((string.map(|s| Atom::String(s, self.tok(pos, end))), end))
+    }
+
+    /// `byte_string = ${ "#\"" ~ (raw_string | byte_escape)* ~ "\"" }`
+    fn byte_string(&mut self, pos: usize) -> Option<AtomForm> {
+        let (parts, end) = self.string_parts(Rule::byte_string, pos)?;
+        let bytes = SexprParser::decode_byte_string(parts, self.source);
+        Some((bytes.map(|b| Atom::ByteString(b, self.tok(pos, end))), end))
+    }
+
+    /// `regexp = ${ regexp_kind ~ (string | byte_string) }`
+    fn regexp(&mut self, pos: usize) -> Option<AtomForm> {
+        self.rule(Rule::regexp, pos, |r| {
+            let start = r.rule(Rule::regexp_kind, pos, |r| {
+                r.literal(pos, "#rx").or_else(|| r.literal(pos, "#px"))
+            })?;
+            let kind = match &r.text[pos..start] {
+                "#px" => RegexpKind::Px,
+                _ => RegexpKind::Rx,
+            };
+            if let Some((parts, end)) = r.string_parts(Rule::string, start) {
+                let pattern = SexprParser::decode_string(parts, r.opts, r.source);
+                let tok = r.tok(pos, end);
+                return Some((pattern.map(|p| Atom::Regexp(p, kind, tok)), end));
+            }
+            let (parts, end) = r.string_parts(Rule::byte_string, start)?;
+            let pattern = SexprParser::decode_byte_string(parts, r.source);
+            let tok = r.tok(pos, end);
+            Some((pattern.map(|p| Atom::ByteRegexp(p, kind, tok)), end))
+        })
+    }
+
+    /// Matches a string or a byte string, which is given by its rule, giving back its parts.
+    fn string_parts(&mut self, rule: Rule, pos: usize) -> Option<(Vec<StringPart<'a>>, usize)> {
+        let (open, escape_rule) = match rule {
+            Rule::string => ("\"", Rule::escape),
+            _ => ("#\"", Rule::byte_escape),
+        };
+        let text = self.text;
+        self.rule(rule, pos, |r| {
+            let mut pos = r.literal(pos, open)?;
+            let mut parts = Vec::new();
+            loop {
+                let raw = r.rule(Rule::raw_string, pos, |r| {
+                    r.repeat_chars(pos, |b| b != b'\\' && b != b'"')
+                });
+                if let Some(end) = raw {
+                    let text = &text[pos..end];
+                    parts.push(StringPart {
+                        start: pos,
+                        text,
+                        escape: None,
+                    });
+                    pos = end;
+                    continue;
+                }
+                let Some((rule, end)) = r.rule(escape_rule, pos, |r| r.escape(pos)) else {
+                    break;
+                };
+                let escape = Some((rule, &text[pos + 1..end]));
+                parts.push(StringPart {
+                    start: pos,
+                    text: &text[pos..end],
+                    escape,
+                });
+                pos = end;
+            }
+            Some((parts, r.literal(pos, "\"")?))
+        })
+    }
+
+    /// `escape = ${ "\\" ~ (predefined | octal | byte | legacy_unicode | unicode | continuation) }`
+    fn escape(&mut self, pos: usize) -> Option<(Rule, usize)> {
+        let start = self.literal(pos, "\\")?;
+        self.first_of(
+            start,
+            &[
+                (Rule::predefined, |r, pos| {
+                    r.repeat(pos, 1, 1, |b| b"abtnvfre\"'\\".contains(&b))
+                }),
+                (Rule::octal, |r, pos| {
+                    r.repeat(pos, 1, 3, |b| matches!(b, b'0'..=b'7'))
+                }),
+                (Rule::byte, |r, pos| r.hex(r.literal(pos, "x")?, 2)),
+                (Rule::legacy_unicode, |r, pos| {
+                    let digits = r.literal(pos, "u{")?;
+                    r.literal(r.repeat(digits, 1, 6, |b| b.is_ascii_hexdigit())?, "}")
+                }),
+                (Rule::unicode, Self::unicode),
+                (Rule::continuation, Self::newline),
+            ],
+        )
+    }
+
+    /// `unicode = @{ "u" ~ hex{1, 4} | "U" ~ hex{1, 8} }`, which is also how a char is written
+    fn unicode(&self, pos: usize) -> Option<usize> {
+        match self.bytes.get(pos) {
+            Some(b'u') => self.hex(pos + 1, 4),
+            Some(b'U') => self.hex(pos + 1, 8),
+            _ => None,
+        }
+    }
+
+    fn hex(&self, pos: usize, max: usize) -> Option<usize> {
+        self.repeat(pos, 1, max, |b| b.is_ascii_hexdigit())
+    }
+
+    /// `here_string = ${ "#<<" ~ PUSH(here_terminator) ~ NEWLINE ~ (POP ~ &(NEWLINE | EOI) |
+    /// here_body ~ NEWLINE ~ POP) }`
+    fn here_string(&mut self, pos: usize) -> Option<AtomForm> {
+        let bytes = self.bytes;
+        let (body, end) = self.rule(Rule::here_string, pos, |r| {
+            let start = r.literal(pos, "#<<")?;
+            let name_end = r.rule(Rule::here_terminator, start, |r| {
+                r.repeat_chars(start, |b| b != b'\n' && b != b'\r')
+            })?;
+            let terminator = &bytes[start..name_end];
+            let body = r.newline(name_end)?;
+            if bytes[body..].starts_with(terminator) {
+                let end = body + terminator.len();
+                if r.newline(end).is_some() || r.eoi(end).is_some() {
+                    return Some((body..body, end));
+                }
+            }
+            let body_end = r.rule(Rule::here_body, body, |r| {
+                let mut end = body;
+                while end < bytes.len() && !r.is_here_end(end, terminator) {
+                    end = r.any(end)?;
+                }
+                (end > body).then_some(end)
+            })?;
+            let end = r.newline(body_end)?;
+            bytes[end..]
+                .starts_with(terminator)
+                .then_some((body..body_end, end + terminator.len()))
+        })?;
+        let body = self.text[body].to_string();
+        Some((Ok(Atom::String(body, self.tok(pos, end))), end))
+    }
+
+    /// `here_end = _{ NEWLINE ~ PEEK ~ &(NEWLINE | EOI) }`
+    fn is_here_end(&self, pos: usize, terminator: &[u8]) -> bool {
+        let Some(start) = self.newline(pos) else {
+            return false;
+        };
+        let end = start + terminator.len();
+        self.bytes[start..].starts_with(terminator)
+            && (end == self.bytes.len() || self.newline(end).is_some())
+    }
+
+    /// `keyword = ${ "#:" ~ symbol }`
+    fn keyword(&mut self, pos: usize) -> Option<AtomForm> {
+        self.rule(Rule::keyword, pos, |r| {
+            let start = r.literal(pos, "#:")?;
+            let (parts, end) = r.symbol_parts(start)?;
+            let name = SexprParser::symbol_name(parts, r.opts);
+            Some((Ok(Atom::Keyword(name, r.tok(pos, end))), end))
+        })
+    }
+
+    fn symbol(&mut self, pos: usize) -> Option<AtomForm> {
+        let (parts, end) = self.symbol_parts(pos)?;
+        let name = SexprParser::symbol_name(parts, self.opts);
+        Some((Ok(Atom::Symbol(name, self.tok(pos, end))), end))
+    }
+
+    /// `symbol = ${ !("#<<" | "#|") ~ (symbol_plain | symbol_pipe | symbol_escape)+ }`, giving
+    /// back its parts
+    #[allow(clippy::type_complexity)]
+    fn symbol_parts(&mut self, pos: usize) -> Option<(Vec<(Rule, &'a str)>, usize)> {
+        let text = self.text;
+        self.rule(Rule::symbol, pos, |r| {
+            if text[pos..].starts_with("#<<") || text[pos..].starts_with("#|") {
+                return None;
+            }
+            let mut parts = Vec::new();
+            let mut end = pos;
+            while let Some((rule, part_end)) = r.first_of(
+                end,
+                &[
+                    (Rule::symbol_plain, Self::symbol_plain),
+                    (Rule::symbol_pipe, |r, pos| {
+                        let start = r.literal(pos, "|")?;
+                        let len = r.text[start..].find('|')?;
+                        Some(start + len + 1)
+                    }),
+                    (Rule::symbol_escape, |r, pos| r.any(r.literal(pos, "\\")?)),
+                ],
+            ) {
+                parts.push((rule, &text[end..part_end]));
+                end = part_end;
+            }
+            (!parts.is_empty()).then_some((parts, end))
+        })
+    }
+
+    /// `symbol_plain = @{ symchar+ }`, where a `symchar` is anything but whitespace, brackets,
+    /// `"`, `#;`, `|` and `\`
+    fn symbol_plain(&self, pos: usize) -> Option<usize> {
+        let mut end = pos;
+        while let Some(&b) = self.bytes.get(end) {
+            match b {
+                b' ' | b'\n' | b'\r' | b'(' | b'[' | b'{' | b')' | b']' | b'}' | b'"' | b'|'
+                | b'\\' => break,
+                b'#' if self.bytes.get(end + 1) == Some(&b';') => break,
+                _ => end += 1,
+            }
+        }
+        (end > pos).then_some(end)
+    }
+
+    /// Skips the whitespace and the comments at `pos`, like pest does in between the parts of
+    /// the rules that aren't atomic.
+    fn skip(&mut self, mut pos: usize) -> usize {
+        loop {
+            let rest = &self.bytes[pos..];
+            pos = match rest.first() {
+                Some(b' ' | b'\n' | b'\r') => pos + 1,
+                Some(b';') => self.line_end(pos),
+                _ if rest.starts_with(b"#|") => match self.block_comment(pos) {
+                    Some(end) => end,
+                    None => return pos,
+                },
+                _ if rest.starts_with(b"#!eof") => self.bytes.len(),
+                _ if rest.starts_with(b"#! ") || rest.starts_with(b"#!/") => self.shebang_end(pos),
+                _ => return pos,
+            }
+        }
+    }
+
+    /// `block_comment = @{ "#|" ~ (block_comment | !("|#" | "#|") ~ ANY)* ~ "|#" }`
+    fn block_comment(&mut self, pos: usize) -> Option<usize> {
+        let mut depth = 0;
+        let mut end = pos;
+        while end < self.bytes.len() {
+            if self.bytes[end..].starts_with(b"#|") {
+                depth += 1;
+                end += 2;
+            } else if self.bytes[end..].starts_with(b"|#") {
+                depth -= 1;
+                end += 2;
+                if depth == 0 {
+                    return Some(end);
+                }
+            } else {
+                end += 1;
+            }
+        }
+        None
+    }
+
+    /// Finds the end of a line comment, which is right before the end of its line.
+    fn line_end(&self, pos: usize) -> usize {
+        self.repeat(pos, 0, usize::MAX, |b| b != b'\n' && b != b'\r')
+            .unwrap_or(pos)
+    }
+
+    /// Finds the end of a `#!` comment, which goes on to the next line after a backslash.
+    fn shebang_end(&self, pos: usize) -> usize {
+        let mut end = pos + 3;
+        loop {
+            end = self.line_end(end);
+            match end > pos + 3 && self.bytes[end - 1] == b'\\' {
+                true => match self.newline(end) {
+                    Some(next) => end = next,
+                    None => return end,
+                },
+                false => return end,
+            }
+        }
+    }
+
+    fn literal(&self, pos: usize, literal: &str) -> Option<usize> {
+        let end = pos + literal.len();
+        (self.bytes.get(pos..end) == Some(literal.as_bytes())).then_some(end)
+    }
+
+    /// Matches a literal written in any case, like `^"#e"`.
+    fn insensitive(&self, pos: usize, literal: &str) -> Option<usize> {
+        let end = pos + literal.len();
+        let text = self.bytes.get(pos..end)?;
+        text.eq_ignore_ascii_case(literal.as_bytes()).then_some(end)
+    }
+
+    /// `NEWLINE = { "\n" | "\r\n" | "\r" }`
+    fn newline(&self, pos: usize) -> Option<usize> {
+        (self.literal(pos, "\n"))
+            .or_else(|| self.literal(pos, "\r\n"))
+            .or_else(|| self.literal(pos, "\r"))
+    }
+
+    /// `ANY`, which is a whole char.
+    fn any(&self, pos: usize) -> Option<usize> {
+        let c = self.text[pos..].chars().next()?;
+        Some(pos + c.len_utf8())
+    }
+
+    fn is_alphabetic(&self, pos: usize) -> bool {
+        self.text[pos..].chars().next().is_some_and(ALPHABETIC)
+    }
+
+    /// Matches `min` to `max` ASCII bytes that `matches`.
+    fn repeat(
+        &self,
+        pos: usize,
+        min: usize,
+        max: usize,
+        matches: impl Fn(u8) -> bool,
+    ) -> Option<usize> {
+        let len = (self.bytes[pos..].iter())
+            .take(max)
+            .take_while(|&&b| matches(b))
+            .count();
+        (len >= min).then_some(pos + len)
+    }
+
+    /// Matches one or more chars, up to an ASCII byte that doesn't match.
+    fn repeat_chars(&self, pos: usize, matches: impl Fn(u8) -> bool) -> Option<usize> {
+        let len = (self.bytes[pos..].iter())
+            .take_while(|&&b| !b.is_ascii() || matches(b))
+            .count();
+        (len > 0).then_some(pos + len)
+    }
+}
+
+#[cfg(test)]
+mod fast_parser_tests {
+    use super::FastParser;
+    use crate::{
+        errors::ParsingError,
+        sexpr_parser::{ReaderOptions, SexprParser},
+    };
+
+    /// Inputs that go through every kind of token, and the quirks of the grammar.
+    const CORPUS: &[&str] = &[
+        "",
+        "   \n\r\n ",
+        "(define (f x)\n  (+ x 1))\n(f 2)",
+        "[let ([x 1] {y -2}) (list x y +12 -0 007)]",
+        "(a . b) (a b . c) (a . < . b) (. a) (a .) (a . b c) (a . b . c . d)",
+        "(a .'b) (a .#| c |# b) (a .#;c b) (a .5) (a ... b)",
+        "'x `(a ,b ,@c) #'x #`(a #,b #,@c) #&x ' x '  ;c\n y",
+        "'#;a b",
+        "(a '#;b c)",
+        "#;(a b) c #; #; d e f",
+        "(a #;b c #;(d e))",
+        "#; .",
+        ". a",
+        "'. a",
+        "#t #f #true #false #tx #fa #truex #T",
+        "#:key #:a|b| #: #:#<<x #:1",
+        "\"\" \"abc\" \"a\\nb\" \"λ 𝄞\" \"multi\nline\"",
+        "\"unterminated",
+        "\"\\a\\b\\t\\n\\v\\f\\r\\e\\\"\\'\\\\ \\101\\7\\x41\\x4 \\u03bb\\U1F600\\u{41}\\\nx\"",
+        "\"\\ud83d\\ude00\" \"\\ud83d\" \"\\ude00\" \"\\q\" \"\\x\" \"\\u{}\" \"\\777\"",
+        "(+ 1 2.5 1/2 -3/4 1e3 #x1F #e1.5 1+2i +i -inf.0 +nan.0)",
+        "(#b101 #o17 #xFF #d10 #e#x10 #x#e10 #i1/3 #e1e400 #b2 #o8 #xg 1@2 -1.5e-3i +inf.fi)",
+        "(1#.# 1##.5 .5# 1#e2 1/2# 1.#e 1e 1e+ #x1s2 #xe 1d2 1l2 1S2 -.5 +.e1 . .. .5.)",
+        "(1+ 2nd 1_000 0xFF 0x 20f 1. -.5 1; 1'a 1`a 1,a 1#;a)",
+        "+ - +a -> +|a| -\"s\" +; (- 1) (+)",
+        "99999999999999999999999 -99999999999999999999999 #x-ffffffffffffffffffff",
+        "#\\a #\\space #\\SPACE #\\spacex #\\nul #\\nulx #\\( #\\λ #\\λλ #\\ab #\\101 #\\u3bb",
+        "#\\U1F600 #\\u #\\ #\\",
+        "#(1 2 3) #[a] #{b} #3(1) #(a . b) #hash((a . 1)) #s(p 1) #hasheq() #hasheqv() #hashalw()",
+        "#0() #99999999999999999999999(a) #1(1 2) #hash(a) #hash((a . 1) . b) #s() #s(1 2) #s(. a)",
+        "#\"bytes\" #\"\\xff\\u41\" #\"λ\" #rx\"a+\" #px#\"b\" #rx #rxa #px\"\\\"\"",
+        "#<<EOF\nhere\nEOF\n",
+        "#<<EOF\nEOF\n #<<EOF\nEOF #<<\n #<<EOF #<<EOF\n\nEOF #<<A\r\nb\r\nA\r\n #<<A\nAA\nA",
+        "|a b| a\\ b a|b|c \\x |unterminated",
+        "a;b c\n",
+        "; comment at the end",
+        "; comment\n(a)",
+        "#| block #| nested |# |# a #| unterminated",
+        "#! shebang \\\n continued\n(a) #!eof (b",
+        "#!/usr/bin/racket\n(a) #!\\\r\n(b)",
+        "#lang racket\n(provide f)",
+        "(a\tb)",
+        "a#;b",
+        "a#|b|#",
+        "(a]",
+        "(a))",
+        "((a)",
+        ")",
+        "(a #(b . c))",
+        "#<<EOF\nunterminated",
+        "(λ (x) 'λ)\r\n(b)\r(c)",
+        "\u{feff}(a) \u{feff}",
+        "#e #x #b1#e #, #,@ #& #' #` #; #:",
+        "(a . #;b)",
+        "(#t . #f) (1 . 2 . 3)",
+        "1\\",
+        "{a [b (c]}",
+        "#%app #hash #hashx() #sx",
+    ];
+
+    fn assert_same(input: &str, opts: &ReaderOptions) {
+        // compared through Debug, as NaN isn't equal to itself
+        let fast = format!("{:?}", FastParser::parse_all_with(input, opts));
+        let pest = format!("{:?}", SexprParser::parse_all_with(input, opts));
+        assert_eq!(fast, pest, "{:?}", input);
+    }
+
+    /// Reader options that change every part of the syntax that can be changed.
+    fn all_options() -> Vec<ReaderOptions> {
+        vec![
+            ReaderOptions::default(),
+            ReaderOptions {
+                strict: true,
+                long_booleans: false,
+                ..Default::default()
+            },
+            ReaderOptions {
+                case_sensitive: false,
+                square_brackets: false,
+                curly_brackets: false,
+                short_booleans: false,
+                decimal_as_exact: true,
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_same_as_pest() {
+        for input in CORPUS {
+            for opts in all_options() {
+                assert_same(input, &opts);
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_as_pest_on_testfiles() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testfiles");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "rkt") {
+                let input = std::fs::read_to_string(&path).unwrap();
+                assert_same(&input, &ReaderOptions::default());
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_as_pest_on_every_prefix() {
+        // cutting the input anywhere gives all sorts of unterminated forms
+        let input = "(define (f x) ; f\n  [cond (#t 'a) (else `(,x . \"s\\n\"))]) #;(g) \
+                     #(1 -2) #hash((a . #e1.5)) #\\space #:k |p q| #<<E\nbody\nE\n#rx\"a\" 0xF";
+        for (i, _) in input.char_indices() {
+            for opts in all_options() {
+                assert_same(&input[..i], &opts);
+            }
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let opts = ReaderOptions {
+            max_depth: 2,
+            max_forms: 5,
+            ..Default::default()
+        };
+        let deep = |input| {
+            assert_same(input, &opts);
+            match FastParser::parse_all_with(input, &opts) {
+                Err(ParsingError::TooDeep(tok)) => tok.as_str().to_string(),
+                other => panic!("{:?}", other),
+            }
+        };
+        // the first token of the form that is too deep is blamed, even when the form can't be
+        // read, as reading it could need more stack than there is
+        assert_eq!(deep("(a (b))"), "b");
+        assert_eq!(deep("'(a)"), "a");
+        assert_eq!(deep("(a (#3(b)"), "#3(");
+        assert_eq!(deep("('#;a"), "#;");
+        assert_eq!(deep("#| #| #| |# |# |#"), "#|");
+        match FastParser::parse_all_with("a b (c d) e", &opts) {
+            Err(ParsingError::TooManyForms(tok)) => assert_eq!(tok.as_str(), "e"),
+            other => panic!("{:?}", other),
+        }
+        // forms that the grammar reads again after giving up on them are counted once
+        assert!(FastParser::parse_all_with("#(a b) c", &opts).is_ok());
+        assert!(FastParser::parse_all_with("a b c d e", &opts).is_ok());
+    }
+}
@@ -1,27 +1,90 @@
-/// Where the buffer is newline terminated
+use crate::{
+    sexpr::{self, Atom, Sexpr},
+    sexpr_parser::{ReaderOptions, SexprParser},
+};
+
+/// The `#lang` or `#reader` line of a file: the language, and how the file has to be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub lang: String,
+    pub options: ReaderOptions,
+}
+
+/// Finds the `#lang` or `#reader` line of the buffer and blanks it out, see `strip_header`.
 pub fn strip_hashlang(buf: &mut String) -> Option<String> {
-    let mut lines: Vec<&str> = buf.lines().collect();
-    for (i, line) in lines.iter().enumerate() {
+    strip_header(buf).map(|header| header.lang)
+}
+
+/// Like `strip_hashlang`, also giving the reader options that the header asks for. The lines can
+/// end in `\n`, `\r\n` or `\r`, and the file can start with a byte order mark. The header line
+/// is replaced with spaces, so the offsets of the rest of the buffer stay the same.
+pub fn strip_header(buf: &mut String) -> Option<Header> {
+    let ends: Vec<usize> = sexpr::line_breaks(buf).chain([buf.len()]).collect();
+    let mut start = 0;
+    for end in ends {
+        let line = buf[start..end].trim_end_matches(['\n', '\r']);
+        let bom = if start == 0 && line.starts_with('\u{feff}') {
+            '\u{feff}'.len_utf8()
+        } else {
+            0
+        };
+        let line = &line[bom..];
         if line.starts_with('#') {
-            let res = parse_hashlang(line);
-            if res.is_some() {
-                lines.remove(i);
-                *buf = lines.join("\n");
-                return res;
+            if let Some(lang) = parse_hashlang(line) {
+                let options = reader_options(line, &lang);
+                let header = start + bom..start + bom + line.len();
+                buf.replace_range(header, &" ".repeat(line.len()));
+                return Some(Header { lang, options });
             }
         }
+        start = end;
     }
     None
 }
 
+/// Gets the reader options of a header line. The teaching languages read decimals as exact,
+/// and the settings that DrRacket writes after a `#reader`, like `(read-case-sensitive #t)`,
+/// override the ones of the language.
+fn reader_options(line: &str, lang: &str) -> ReaderOptions {
+    let mut options = ReaderOptions {
+        decimal_as_exact: lang.starts_with("htdp") || lang.starts_with("lang/htdp"),
+        ..Default::default()
+    };
+    // the settings are the list after the `#reader(lib ...)` form
+    let settings = line
+        .strip_prefix("#reader")
+        .and_then(|rest| SexprParser::parse_all(rest).ok())
+        .and_then(|mut forms| (forms.len() == 2).then(|| forms.pop().unwrap()));
+    let settings = match settings {
+        Some(Sexpr::List(settings, _)) => settings,
+        _ => return options,
+    };
+    for setting in settings {
+        if let Sexpr::List(setting, _) = setting {
+            if let [Sexpr::Atom(Atom::Symbol(name, _), _), Sexpr::Atom(Atom::Boolean(on, _), _)] =
+                &setting[..]
+            {
+                match name.as_str() {
+                    "read-case-sensitive" => options.case_sensitive = *on,
+                    "read-square-bracket-as-paren" => options.square_brackets = *on,
+                    "read-curly-brace-as-paren" => options.curly_brackets = *on,
+                    "read-decimal-as-inexact" => options.decimal_as_exact = !*on,
+                    _ => {}
+                }
+            }
+        }
+    }
+    options
+}
+
 fn parse_hashlang(line: &str) -> Option<String> {
     if line.starts_with("#lang") {
         let stripped = line.strip_prefix("#lang")?.trim();
         Some(stripped.to_string())
     } else if line.starts_with("#reader") {
-        let stripped = line.strip_prefix("#reader(lib ")?;
-        let idx_end_lang = stripped[1..].find('\"')? + 1;
-        Some(stripped[0..idx_end_lang].trim_matches('\"').to_string())
+        let stripped = line.strip_prefix("#reader(lib ")?.strip_prefix('"')?;
+        let (lang, _) = stripped.split_once('"')?;
+        Some(lang.to_string())
     } else {
         None
     }
@@ -29,13 +92,27 @@ fn parse_hashlang(line: &str) -> Option<String> {
 
 #[cfg(test)]
 mod hashlang_tests {
-    use super::{parse_hashlang, strip_hashlang};
+    use super::{parse_hashlang, strip_hashlang, strip_header, Header};
+    use crate::sexpr_parser::{ReaderOptions, SexprParser};
 
     #[test]
     fn parse_reader_test() {
         let res = parse_hashlang("#reader(lib \"htdp-beginner-reader.ss\" \"lang\")((modname p2-code) (read-case-sensitive #t) (teachpacks ()) (htdp-settings #(#t constructor repeating-decimal #f #t none #f () #f)))");
         assert_eq!(res, Some("htdp-beginner-reader.ss".to_string()))
     }
+
+    #[test]
+    fn parse_broken_reader_test() {
+        for line in [
+            "#reader(lib ",
+            "#reader(lib \"",
+            "#reader(lib é\"x\")",
+            "#reader(lib x\")",
+        ] {
+            assert_eq!(parse_hashlang(line), None, "{:?}", line);
+        }
+    }
+
     #[test]
     fn parse_hashlang_test() {
         let res = parse_hashlang("#lang htdp/bsl");
@@ -53,9 +130,11 @@ mod hashlang_tests {
 "#
         .to_string();
 
+        let len = file.len();
         let res = strip_hashlang(&mut file);
 
-        assert_eq!(file.split('\n').nth(0).unwrap(), "");
+        assert_eq!(file.split('\n').nth(0).unwrap().trim(), "");
+        assert_eq!(file.len(), len);
 
         assert_eq!(res, Some("racket/full".to_string()));
     }
@@ -76,8 +155,86 @@ mod hashlang_tests {
 
         let res = strip_hashlang(&mut file);
 
-        assert_eq!(file.split('\n').nth(2).unwrap(), "");
+        assert_eq!(file.split('\n').nth(2).unwrap().trim(), "");
 
         assert_eq!(res, Some("htdp-intermediate-lambda-reader.ss".to_string()));
     }
+
+    #[test]
+    fn platform_line_endings_test() {
+        let lf = "#lang racket\n(define x 1)\n; done";
+        for (newline, bom) in [("\n", ""), ("\r\n", ""), ("\r", ""), ("\r\n", "\u{feff}")] {
+            let text = format!("{bom}{}", lf.replace('\n', newline));
+            let mut file = text.clone();
+            assert_eq!(strip_hashlang(&mut file), Some("racket".to_string()));
+            // only the header is blanked out, the BOM and the line breaks stay where they were
+            assert_eq!(file.len(), text.len());
+            assert_eq!(file, text.replace("#lang racket", "            "));
+
+            let forms = SexprParser::parse_all(&file).unwrap();
+            assert_eq!(forms.len(), 1);
+            let tok = forms[0].get_decorator();
+            assert_eq!(&text[tok.start()..tok.end()], "(define x 1)");
+            assert_eq!(tok.start_line_col(), (2, 1));
+        }
+
+        // a header that isn't on the first line
+        let mut file = "; a comment\r\n#lang htdp/bsl\r\n(f 1.5)".to_string();
+        assert_eq!(strip_hashlang(&mut file), Some("htdp/bsl".to_string()));
+        assert_eq!(
+            file,
+            format!("; a comment\r\n{}\r\n(f 1.5)", " ".repeat(14))
+        );
+    }
+
+    #[test]
+    fn header_options_test() {
+        let mut file = "#lang racket\n(define x 1.5)\n".to_string();
+        let header = strip_header(&mut file).unwrap();
+        assert_eq!(
+            header,
+            Header {
+                lang: "racket".to_string(),
+                options: ReaderOptions::default()
+            }
+        );
+
+        let mut file = "#lang htdp/bsl\n(define x 1.5)\n".to_string();
+        let options = strip_header(&mut file).unwrap().options;
+        assert!(options.decimal_as_exact && options.case_sensitive);
+        let x = SexprParser::parse_all_with(&file, &options).unwrap();
+        assert_eq!(x[0].clone().untag().to_string(), "(define x 3/2)");
+    }
+
+    #[test]
+    fn case_insensitive_reader_test() {
+        let mut file = r#"#reader(lib "htdp-beginner-reader.ss" "lang")((modname p) (read-case-sensitive #f) (teachpacks ()) (htdp-settings #(#f constructor repeating-decimal #f #t none #f () #f)))
+(define (MyFunc x) x)
+(myfunc |MyFunc|)
+"#
+        .to_string();
+        let header = strip_header(&mut file).unwrap();
+        assert_eq!(header.lang, "htdp-beginner-reader.ss");
+        assert!(!header.options.case_sensitive && header.options.decimal_as_exact);
+
+        let forms = SexprParser::parse_all_with(&file, &header.options).unwrap();
+        assert_eq!(
+            forms[0].clone().untag().to_string(),
+            "(define (myfunc x) x)"
+        );
+        // the parts between pipes are kept as they are
+        assert_eq!(forms[1].clone().untag().to_string(), "(myfunc MyFunc)");
+    }
+
+    #[test]
+    fn reader_settings_test() {
+        let mut file = r#"#reader(lib "htdp-advanced-reader.ss" "lang")((modname p) (read-case-sensitive #t) (read-square-bracket-as-paren #f) (read-decimal-as-inexact #t))
+(cond [else 1])
+"#
+        .to_string();
+        let options = strip_header(&mut file).unwrap().options;
+        assert!(!options.square_brackets && options.curly_brackets);
+        assert!(!options.decimal_as_exact);
+        assert!(SexprParser::parse_all_with(&file, &options).is_err());
+    }
 }
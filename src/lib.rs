@@ -1,7 +1,18 @@
 pub mod sexpr;
+pub mod number;
 pub mod sexpr_parser;
+pub mod fast_parser;
+pub mod stream;
+pub mod document;
+mod brackets;
+mod depth;
+pub mod cst;
+pub mod comments;
 pub mod errors;
 pub mod hashlang;
+pub mod wxme;
 pub mod config;
 pub mod wellformed;
 pub mod prog;
+#[cfg(test)]
+pub(crate) mod test_util;
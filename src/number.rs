@@ -0,0 +1,273 @@
+use std::num::IntErrorKind;
+
+use num_bigint::{BigInt, Sign};
+use num_integer::Integer;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+
+#[derive(PartialEq, Debug, Clone)]
+/// Represents a real number of Racket's numeric tower, used for the parts of complex numbers.
+pub enum Real {
+    Integer(i64),
+    BigInteger(BigInt), // only for integers that don't fit in an i64, see `Real::integer`
+    Rational(BigInt, BigInt), // numerator and denominator, always normalized (see `Real::rational`)
+    Float(f64),
+}
+
+/// The exactness forced by a `#e` or `#i` prefix.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub(crate) enum Exactness {
+    Exact,
+    Inexact,
+}
+
+/// A number as read by the reader, either real or complex.
+#[derive(PartialEq, Debug, Clone)]
+pub(crate) enum Number {
+    Real(Real),
+    Complex(Real, Real),
+}
+
+impl Real {
+    /// Makes an integer out of the given one, which is only kept big if it doesn't fit in an i64.
+    pub fn integer(int: BigInt) -> Real {
+        match int.to_i64() {
+            Some(int) => Real::Integer(int),
+            None => Real::BigInteger(int),
+        }
+    }
+
+    /// Makes a normalized rational out of the given numerator and denominator, meaning that the
+    /// fraction is reduced, the sign is in the numerator, and it becomes an integer if the
+    /// denominator is one.
+    pub fn rational(num: BigInt, den: BigInt) -> Result<Real, String> {
+        if den.is_zero() {
+            return Err("Division by zero".to_string());
+        }
+        let g = num.gcd(&den);
+        let (mut num, mut den) = (num / &g, den / &g);
+        if den.is_negative() {
+            num = -num;
+            den = -den;
+        }
+        if den.is_one() {
+            Ok(Real::integer(num))
+        } else {
+            Ok(Real::Rational(num, den))
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        !matches!(self, Real::Float(_))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Real::Integer(i) => *i as f64,
+            Real::BigInteger(i) => i.to_f64().unwrap_or(f64::NAN),
+            Real::Rational(n, d) => match (n.to_f64(), d.to_f64()) {
+                (Some(n), Some(d)) => n / d,
+                _ => f64::NAN,
+            },
+            Real::Float(f) => *f,
+        }
+    }
+
+    fn is_exact_zero(&self) -> bool {
+        matches!(self, Real::Integer(0))
+    }
+
+    fn to_inexact(&self) -> Real {
+        Real::Float(self.to_f64())
+    }
+}
+
+impl Number {
+    /// Makes a complex number out of the given parts. Like in Racket, an exact zero imaginary part
+    /// gives back a real, and an inexact part makes the other one inexact too, unless it's an
+    /// exact zero.
+    pub(crate) fn rectangular(re: Real, im: Real) -> Number {
+        if im.is_exact_zero() {
+            Number::Real(re)
+        } else if re.is_exact() && im.is_exact() || re.is_exact_zero() {
+            Number::Complex(re, im)
+        } else {
+            Number::Complex(re.to_inexact(), im.to_inexact())
+        }
+    }
+
+    /// Makes a complex number out of the given magnitude and angle.
+    pub(crate) fn polar(mag: Real, ang: Real) -> Number {
+        if ang.is_exact_zero() {
+            Number::Real(mag)
+        } else {
+            let (mag, ang) = (mag.to_f64(), ang.to_f64());
+            Number::rectangular(Real::Float(mag * ang.cos()), Real::Float(mag * ang.sin()))
+        }
+    }
+}
+
+/// Exact numbers with a larger exponent than this are rejected, as something like #e1e999999999
+/// would otherwise take up all the memory.
+const MAX_EXACT_EXPONENT: u32 = 100_000;
+const TOO_LARGE: &str = "The exponent is too large to read the number exactly";
+
+/// Reads the digits of an unsigned integer in the given radix. The grammar makes sure that the
+/// digits are valid, but this still doesn't panic if they aren't.
+pub(crate) fn read_uinteger(digits: &str, radix: u32) -> Result<BigInt, String> {
+    if digits.is_empty() {
+        return Ok(BigInt::zero());
+    }
+    BigInt::parse_bytes(digits.as_bytes(), radix)
+        .ok_or_else(|| format!("`{}` is not a valid integer in radix {}", digits, radix))
+}
+
+/// Reads the text of a real number in the given radix, as matched by the `real<n>` rules of the
+/// grammar. The exactness is the natural one of the number, unless forced by `exactness`.
+pub(crate) fn read_real(
+    text: &str,
+    radix: u32,
+    exactness: Option<Exactness>,
+) -> Result<Real, String> {
+    let (neg, unsigned) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let lower = unsigned.to_ascii_lowercase();
+    if lower.starts_with("inf.") || lower.starts_with("nan.") {
+        if exactness == Some(Exactness::Exact) {
+            return Err(format!("No exact representation for {}", text));
+        }
+        let f = if lower.starts_with("nan.") {
+            f64::NAN
+        } else if neg {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        };
+        return Ok(Real::Float(f));
+    }
+
+    // `#` stands for a digit that isn't known, which makes the number inexact
+    let inexact_digits = lower.contains('#');
+    let lower = lower.replace('#', "0");
+    let sign = if neg { -1 } else { 1 };
+
+    let (value, natural_exact) = if let Some((num, den)) = lower.split_once('/') {
+        let num: BigInt = read_uinteger(num, radix)? * sign;
+        let den = read_uinteger(den, radix)?;
+        let inexact = match exactness {
+            Some(exactness) => exactness == Exactness::Inexact,
+            None => inexact_digits,
+        };
+        if den.is_zero() && inexact {
+            // an inexact division by zero is an infinity, like `#i1/0`, or not a number
+            let f = match num.sign() {
+                Sign::Plus => f64::INFINITY,
+                Sign::Minus => f64::NEG_INFINITY,
+                Sign::NoSign => f64::NAN,
+            };
+            (Real::Float(f), false)
+        } else {
+            (Real::rational(num, den)?, true)
+        }
+    } else {
+        let markers: &[char] = if radix == 16 {
+            &['s', 'l']
+        } else {
+            &['s', 'l', 'd', 'e', 'f']
+        };
+        let (mantissa, exp) = match lower.split_once(markers) {
+            Some((mantissa, exp)) => (mantissa, Some(read_exponent(exp, radix)?)),
+            None => (lower.as_str(), None),
+        };
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let is_decimal = exp.is_some() || mantissa.contains('.');
+
+        if exactness == Some(Exactness::Exact) || !is_decimal && !inexact_digits {
+            let digits = format!("{}{}", int, frac);
+            let mantissa = read_uinteger(&digits, radix)? * sign;
+            let scale = exp.unwrap_or(0) as i64 - frac.len() as i64;
+            let scale_pow = match u32::try_from(scale.unsigned_abs()) {
+                Ok(scale) if scale <= MAX_EXACT_EXPONENT => BigInt::from(radix).pow(scale),
+                _ => return Err(TOO_LARGE.to_string()),
+            };
+            let value = if scale >= 0 {
+                Real::integer(mantissa * scale_pow)
+            } else {
+                Real::rational(mantissa, scale_pow)?
+            };
+            (value, !is_decimal)
+        } else if radix == 10 {
+            let float = format!(
+                "{}{}.{}e{}",
+                if neg { "-" } else { "" },
+                if int.is_empty() { "0" } else { int },
+                if frac.is_empty() { "0" } else { frac },
+                exp.unwrap_or(0)
+            );
+            (
+                Real::Float(float.parse::<f64>().map_err(|e| e.to_string())?),
+                false,
+            )
+        } else {
+            let mantissa = int
+                .chars()
+                .chain(frac.chars())
+                .filter_map(|c| c.to_digit(radix))
+                .fold(0.0, |acc, d| acc * radix as f64 + d as f64);
+            let scale = exp.unwrap_or(0).saturating_sub(frac.len() as i32);
+            let float = match mantissa {
+                // zero times an infinite power is still zero
+                0.0 => sign as f64 * 0.0,
+                _ => sign as f64 * mantissa * (radix as f64).powi(scale),
+            };
+            (Real::Float(float), false)
+        }
+    };
+
+    match exactness {
+        Some(Exactness::Exact) => Ok(value),
+        None if natural_exact && !inexact_digits => Ok(value),
+        _ => Ok(value.to_inexact()),
+    }
+}
+
+/// Reads the exponent of a decimal. An exponent that doesn't fit is saturated, as it still gives
+/// an infinity or a zero when read inexactly, and is too large when read exactly.
+fn read_exponent(exp: &str, radix: u32) -> Result<i32, String> {
+    i32::from_str_radix(exp, radix).or_else(|e| match e.kind() {
+        IntErrorKind::PosOverflow => Ok(i32::MAX),
+        IntErrorKind::NegOverflow => Ok(i32::MIN),
+        _ => Err(format!("`{}` is not a valid exponent", exp)),
+    })
+}
+
+/// Reads the text of an imaginary part, as matched by the `imag<n>` rules of the grammar.
+pub(crate) fn read_imag(
+    text: &str,
+    radix: u32,
+    exactness: Option<Exactness>,
+) -> Result<Real, String> {
+    let text = &text[..text.len() - 1]; // trimming the `i`
+    match text {
+        "+" => read_real("1", radix, exactness),
+        "-" => read_real("-1", radix, exactness),
+        _ => read_real(text, radix, exactness),
+    }
+}
+
+impl std::fmt::Display for Real {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Real::Integer(i) => write!(f, "{}", i),
+            Real::BigInteger(i) => write!(f, "{}", i),
+            Real::Rational(n, d) => write!(f, "{}/{}", n, d),
+            Real::Float(fl) if fl.is_nan() => write!(f, "+nan.0"),
+            Real::Float(fl) if fl.is_infinite() && *fl > 0.0 => write!(f, "+inf.0"),
+            Real::Float(fl) if fl.is_infinite() => write!(f, "-inf.0"),
+            // the debug format always has a decimal point or an exponent, like Racket
+            Real::Float(fl) => write!(f, "{:?}", fl),
+        }
+    }
+}
@@ -1,6 +1,140 @@
-use crate::sexpr::{Sexpr, TokInfo};
+use std::io;
+
+use crate::{
+    comments::{leading_range, Comment},
+    cst::Cst,
+    errors::ParsingError,
+    hashlang::strip_header,
+    sexpr::{Atom, Sexpr, TokInfo},
+    sexpr_parser::ReaderOptions,
+    wxme,
+};
 
 pub struct Program {
     pub hashlang: String,
     pub body: Vec<Sexpr<TokInfo>>,
+    pub comments: Vec<Comment>, // every comment of the program, in order
+}
+
+impl Program {
+    /// Reads the program of a file, which can also be one that DrRacket saved in the WXME format,
+    /// with images or comment boxes in it (see `wxme::decode`). The rest of the file is read
+    /// without its `#lang` or `#reader` line, with the reader options that the line asks for.
+    pub fn read(input: &[u8]) -> Result<Program, ParsingError> {
+        let mut text = if wxme::is_wxme(input) {
+            wxme::decode(input)?.text
+        } else {
+            String::from_utf8(input.to_vec())
+                .map_err(|e| ParsingError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?
+        };
+        let header = strip_header(&mut text);
+        let opts = header
+            .as_ref()
+            .map_or_else(ReaderOptions::default, |header| header.options.clone());
+        let (cst, body) = Cst::parse_with_sexprs(&text, &opts)?;
+        let hashlang = header.map_or(String::new(), |header| header.lang);
+        Ok(Program {
+            hashlang,
+            body,
+            comments: cst.comments(),
+        })
+    }
+
+    /// Gets the top-level definition of the given name, either `(define name ...)` or
+    /// `(define (name ...) ...)`.
+    pub fn definition(&self, name: &str) -> Option<&Sexpr<TokInfo>> {
+        self.body.iter().find(|form| match form {
+            Sexpr::List(l, _) => match &l[..] {
+                [Sexpr::Atom(Atom::Symbol(define, _), _), header, ..] if define == "define" => {
+                    match header {
+                        Sexpr::Atom(Atom::Symbol(sym, _), _) => sym == name,
+                        Sexpr::List(h, _) | Sexpr::DottedList(h, _, _) => matches!(
+                            h.first(),
+                            Some(Sexpr::Atom(Atom::Symbol(sym, _), _)) if sym == name
+                        ),
+                        _ => false,
+                    }
+                }
+                _ => false,
+            },
+            _ => false,
+        })
+    }
+
+    /// Gets the comments right before the given top-level form, see `comments::leading_comments`.
+    pub fn comments_before(&self, form: &Sexpr<TokInfo>) -> Vec<&Comment> {
+        let tok = form.get_decorator();
+        let index = self
+            .body
+            .binary_search_by_key(&tok.start(), |f| f.get_decorator().start())
+            .ok()
+            .filter(|&i| self.body[i].get_decorator() == tok);
+        match index {
+            Some(i) => self.comments[leading_range(&self.comments, &self.body, i)]
+                .iter()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Gets the comments right before the definition of the given name, like its purpose
+    /// statement.
+    pub fn comments_before_definition(&self, name: &str) -> Vec<&Comment> {
+        self.definition(name)
+            .map_or(Vec::new(), |def| self.comments_before(def))
+    }
+}
+
+#[cfg(test)]
+mod prog_tests {
+    use super::Program;
+    use crate::{
+        comments::parse_comments, errors::ParsingError, hashlang::strip_hashlang,
+        sexpr_parser::SexprParser,
+    };
+
+    #[test]
+    fn test_comments_before_definition() {
+        let mut input = "#lang racket\n;; the answer\n(define x 42) ; x\n\n; adds one\n; to n\n\
+            (define (my-func n)\n  ; inside\n  (+ n 1))\n"
+            .to_string();
+        let hashlang = strip_hashlang(&mut input).unwrap();
+        let prog = Program {
+            hashlang,
+            body: SexprParser::parse_all(&input).unwrap(),
+            comments: parse_comments(&input).unwrap(),
+        };
+        let texts = |name| -> Vec<String> {
+            prog.comments_before_definition(name)
+                .iter()
+                .map(|c| c.text.clone())
+                .collect()
+        };
+        assert_eq!(prog.hashlang, "racket");
+        assert_eq!(texts("x"), ["the answer"]);
+        assert_eq!(texts("my-func"), ["adds one", "to n"]);
+        assert!(texts("missing").is_empty());
+        let other = SexprParser::parse_all("(define x 42)").unwrap();
+        assert!(prog.comments_before(&other[0]).is_empty());
+    }
+
+    #[test]
+    fn test_read() {
+        // the reader options of the header are used for the rest of the file
+        let prog = Program::read(b"#lang htdp/bsl\n; x\n(define x 1.5)\n").unwrap();
+        assert_eq!(prog.hashlang, "htdp/bsl");
+        assert_eq!(prog.body[0].clone().untag().to_string(), "(define x 3/2)");
+        assert_eq!(prog.comments_before_definition("x")[0].text, "x");
+
+        let prog = Program::read(b"(a)").unwrap();
+        assert_eq!(prog.hashlang, "");
+        assert!(matches!(
+            Program::read(b"#lang racket\n(a \xff)"),
+            Err(ParsingError::Io(_))
+        ));
+        // a #reader line that can't be a header is read as code
+        assert!(Program::read(b"#reader(lib ").is_err());
+        let prog = Program::read("#reader(lib é\"x\")\n(a)".as_bytes()).unwrap();
+        assert_eq!(prog.hashlang, "");
+    }
 }
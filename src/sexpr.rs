@@ -1,30 +1,80 @@
-use pest::Span;
+use std::sync::Arc;
+
+use num_bigint::BigInt;
+
+use crate::{number::Real, sexpr_parser::SexprParser};
 
 #[derive(PartialEq, Debug, Clone)]
 /// Represents a decorated S-expression value with extended type-assigned atoms.
 pub enum Sexpr<D> {
     Atom(Atom<D>, D),
     List(Vec<Sexpr<D>>, D),
+    /// A vector literal, like #(1 2 3). The optional length is the one given in the length-prefixed
+    /// form, like #3(1 2), which can be more than the elements, as they're only the ones written.
+    Vector(Vec<Sexpr<D>>, Option<usize>, D),
+    /// An improper list, like (a b . c). The first argument are the elements before the dot, and
+    /// the second is the tail after the dot.
+    DottedList(Vec<Sexpr<D>>, Box<Sexpr<D>>, D),
+    /// A hash table literal, like #hash((a . 1) (b . 2)), with its key-value pairs in order.
+    Hash(HashKind, Vec<(Sexpr<D>, Sexpr<D>)>, D),
+    /// A prefab structure literal, like #s(point 1 2). The first argument is the prefab key, which
+    /// is either a symbol or a list like (point 1), and the second one are the fields.
+    Prefab(Box<Sexpr<D>>, Vec<Sexpr<D>>, D),
 }
 
 #[derive(PartialEq, Debug, Clone)]
 /// Represents the atoms of the S-expression, decorated as well.
 pub enum Atom<D> {
     Symbol(String, D),
+    Keyword(String, D), // a keyword, like #:when. The string is the name without the #:
     // Additional types for commodity
     String(String, D),
+    ByteString(Vec<u8>, D),             // a byte string, like #"abc"
+    Regexp(String, RegexpKind, D),      // a regexp literal, like #rx"a+" or #px"\\d+"
+    ByteRegexp(Vec<u8>, RegexpKind, D), // a byte regexp literal, like #rx#"a+" or #px#"\\d+"
+    Char(char, D),                      // a character constant, like #\a, #\space or #\u3BB
     Integer(i64, D),
+    BigInteger(BigInt, D), // an integer that doesn't fit in an i64, see `Real::integer`
+    Rational(BigInt, BigInt, D), // an exact rational, like 1/3. Always in lowest terms, see `Real::rational`
     Float(f64, D),
+    Complex(Real, Real, D), // a complex number, like 1+2i. The parts are the real and imaginary ones
     Boolean(bool, D),
     Quoted(Box<Sexpr<D>>, D), // this is a quoted sexpr value, like 'abc or '(1 "bla" #t)
     QuasiQuoted(Box<Sexpr<D>>, D), // this is a quasiquoted sexpr value, like `abc or `(1 "bla" #t)
     Unquoted(Box<Sexpr<D>>, D), // this is an unquoted sexpr value, like ,abc or ,(1 "bla" #t)
+    UnquoteSpliced(Box<Sexpr<D>>, D), // this is an unquote-spliced sexpr value, like ,@abc
+    SyntaxQuoted(Box<Sexpr<D>>, D), // the syntax versions of the above, like #'abc
+    QuasiSyntaxQuoted(Box<Sexpr<D>>, D), // like #`abc
+    Unsyntaxed(Box<Sexpr<D>>, D), // like #,abc
+    UnsyntaxSpliced(Box<Sexpr<D>>, D), // like #,@abc
+    Boxed(Box<Sexpr<D>>, D),  // a box literal, like #&abc
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// The syntax of a regexp literal, either #rx for `regexp` or #px for `pregexp`.
+pub enum RegexpKind {
+    Rx,
+    Px,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// The equality a hash table literal uses to compare keys, given by its prefix.
+pub enum HashKind {
+    Equal,       // #hash
+    Eq,          // #hasheq
+    Eqv,         // #hasheqv
+    EqualAlways, // #hashalw
 }
 
 impl<'a, D: 'a> Sexpr<D> {
     pub fn get_decorator(&'a self) -> &'a D {
         match self {
-            Sexpr::Atom(_, span) | Sexpr::List(_, span) => span,
+            Sexpr::Atom(_, span)
+            | Sexpr::List(_, span)
+            | Sexpr::Vector(_, _, span)
+            | Sexpr::DottedList(_, _, span)
+            | Sexpr::Hash(_, _, span)
+            | Sexpr::Prefab(_, _, span) => span,
         }
     }
 }
@@ -33,13 +83,27 @@ impl<'a, D: 'a> Atom<D> {
     pub fn get_decorator(&'a self) -> &'a D {
         match self {
             Atom::Symbol(_, p)
+            | Atom::Keyword(_, p)
             | Atom::String(_, p)
+            | Atom::ByteString(_, p)
+            | Atom::Regexp(_, _, p)
+            | Atom::ByteRegexp(_, _, p)
+            | Atom::Char(_, p)
             | Atom::Integer(_, p)
+            | Atom::BigInteger(_, p)
+            | Atom::Rational(_, _, p)
             | Atom::Float(_, p)
+            | Atom::Complex(_, _, p)
             | Atom::Boolean(_, p)
             | Atom::Quoted(_, p)
             | Atom::QuasiQuoted(_, p)
-            | Atom::Unquoted(_, p) => p,
+            | Atom::Unquoted(_, p)
+            | Atom::UnquoteSpliced(_, p)
+            | Atom::SyntaxQuoted(_, p)
+            | Atom::QuasiSyntaxQuoted(_, p)
+            | Atom::Unsyntaxed(_, p)
+            | Atom::UnsyntaxSpliced(_, p)
+            | Atom::Boxed(_, p) => p,
         }
     }
 }
@@ -50,13 +114,35 @@ impl<D> std::fmt::Display for Sexpr<D> {
             Sexpr::Atom(atom, _) => {
                 write!(f, "{}", atom)
             }
-            Sexpr::List(list, _) => {
+            Sexpr::List(list, _) => write_list(f, list),
+            Sexpr::Vector(vector, len, _) => {
+                write!(f, "#")?;
+                if let Some(len) = len {
+                    write!(f, "{}", len)?;
+                }
+                write_list(f, vector)
+            }
+            Sexpr::DottedList(list, tail, _) => {
                 write!(f, "(")?;
-                for (i, item) in list.iter().enumerate() {
+                for item in list {
+                    write!(f, "{} ", item)?;
+                }
+                write!(f, ". {})", tail)
+            }
+            Sexpr::Hash(kind, pairs, _) => {
+                write!(f, "{}(", kind)?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
                     if i > 0 {
                         write!(f, " ")?;
                     }
-                    write!(f, "{}", item)?;
+                    write!(f, "({} . {})", key, value)?;
+                }
+                write!(f, ")")
+            }
+            Sexpr::Prefab(key, fields, _) => {
+                write!(f, "#s({}", key)?;
+                for field in fields {
+                    write!(f, " {}", field)?;
                 }
                 write!(f, ")")
             }
@@ -64,13 +150,55 @@ impl<D> std::fmt::Display for Sexpr<D> {
     }
 }
 
+fn write_list<D>(f: &mut std::fmt::Formatter<'_>, list: &[Sexpr<D>]) -> std::fmt::Result {
+    write!(f, "(")?;
+    for (i, item) in list.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    write!(f, ")")
+}
+
 impl<D> std::fmt::Display for Atom<D> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Atom::Symbol(s, _) => write!(f, "{}", s),
-            Atom::String(s, _) => write!(f, "\"{}\"", s),
+            Atom::Symbol(s, _) => write_symbol(f, s),
+            Atom::Keyword(s, _) => {
+                write!(f, "#:")?;
+                write_symbol(f, s)
+            }
+            Atom::String(s, _) => write_escaped(f, s),
+            Atom::ByteString(b, _) => {
+                write!(f, "#")?;
+                write_bytes_escaped(f, b)
+            }
+            Atom::Regexp(s, kind, _) => {
+                write!(f, "{}", kind)?;
+                write_escaped(f, s)
+            }
+            Atom::ByteRegexp(b, kind, _) => {
+                write!(f, "{}#", kind)?;
+                write_bytes_escaped(f, b)
+            }
+            Atom::Char(c, _) => match char_name(*c) {
+                Some(name) => write!(f, "#\\{}", name),
+                None if c.is_control() => write!(f, "#\\u{:04X}", *c as u32),
+                None => write!(f, "#\\{}", c),
+            },
             Atom::Integer(i, _) => write!(f, "{}", i),
-            Atom::Float(fl, _) => write!(f, "{}", fl),
+            Atom::BigInteger(i, _) => write!(f, "{}", i),
+            Atom::Rational(n, d, _) => write!(f, "{}/{}", n, d),
+            Atom::Float(fl, _) => write!(f, "{}", Real::Float(*fl)),
+            Atom::Complex(re, im, _) => {
+                let im = im.to_string();
+                if im.starts_with('+') || im.starts_with('-') {
+                    write!(f, "{}{}i", re, im)
+                } else {
+                    write!(f, "{}+{}i", re, im)
+                }
+            }
             Atom::Boolean(b, _) => {
                 if *b {
                     write!(f, "#t")
@@ -81,16 +209,137 @@ impl<D> std::fmt::Display for Atom<D> {
             Atom::Quoted(q, _) => write!(f, "'{}", q),
             Atom::QuasiQuoted(q, _) => write!(f, "`{}", q),
             Atom::Unquoted(q, _) => write!(f, ",{}", q),
+            Atom::UnquoteSpliced(q, _) => write!(f, ",@{}", q),
+            Atom::SyntaxQuoted(q, _) => write!(f, "#'{}", q),
+            Atom::QuasiSyntaxQuoted(q, _) => write!(f, "#`{}", q),
+            Atom::Unsyntaxed(q, _) => write!(f, "#,{}", q),
+            Atom::UnsyntaxSpliced(q, _) => write!(f, "#,@{}", q),
+            Atom::Boxed(q, _) => write!(f, "#&{}", q),
+        }
+    }
+}
+
+/// Writes the given symbol name, quoting it if it wouldn't be read back as the same symbol, like
+/// |hello world| or |1|.
+fn write_symbol(f: &mut std::fmt::Formatter<'_>, name: &str) -> std::fmt::Result {
+    if SexprParser::is_plain_symbol(name) {
+        write!(f, "{}", name)
+    } else if !name.contains('|') {
+        write!(f, "|{}|", name)
+    } else {
+        // pipes can't be escaped inside of pipes, so every char gets escaped instead
+        name.chars().try_for_each(|c| write!(f, "\\{}", c))
+    }
+}
+
+/// Writes the given string between quotes, escaping it so that it can be read back.
+fn write_escaped(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\u{7}' => write!(f, "\\a")?,
+            '\u{8}' => write!(f, "\\b")?,
+            '\t' => write!(f, "\\t")?,
+            '\n' => write!(f, "\\n")?,
+            '\u{b}' => write!(f, "\\v")?,
+            '\u{c}' => write!(f, "\\f")?,
+            '\r' => write!(f, "\\r")?,
+            '\u{1b}' => write!(f, "\\e")?,
+            c if c.is_control() => write!(f, "\\u{:04X}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Writes the given bytes between quotes, escaping the ones that aren't printable ASCII.
+fn write_bytes_escaped(f: &mut std::fmt::Formatter<'_>, b: &[u8]) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for byte in b {
+        match byte {
+            b'"' => write!(f, "\\\"")?,
+            b'\\' => write!(f, "\\\\")?,
+            b'\t' => write!(f, "\\t")?,
+            b'\n' => write!(f, "\\n")?,
+            b'\r' => write!(f, "\\r")?,
+            b' '..=b'~' => write!(f, "{}", *byte as char)?,
+            _ => write!(f, "\\{:03o}", byte)?,
         }
     }
+    write!(f, "\"")
+}
+
+impl std::fmt::Display for RegexpKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegexpKind::Rx => write!(f, "#rx"),
+            RegexpKind::Px => write!(f, "#px"),
+        }
+    }
+}
+
+impl std::fmt::Display for HashKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashKind::Equal => write!(f, "#hash"),
+            HashKind::Eq => write!(f, "#hasheq"),
+            HashKind::Eqv => write!(f, "#hasheqv"),
+            HashKind::EqualAlways => write!(f, "#hashalw"),
+        }
+    }
+}
+
+/// Gets the reader name of the given char, if it has one. This is the name that gets printed
+/// back, so aliases like `#\linefeed` map to their canonical name.
+fn char_name(c: char) -> Option<&'static str> {
+    match c {
+        '\0' => Some("nul"),
+        '\u{8}' => Some("backspace"),
+        '\t' => Some("tab"),
+        '\n' => Some("newline"),
+        '\u{b}' => Some("vtab"),
+        '\u{c}' => Some("page"),
+        '\r' => Some("return"),
+        ' ' => Some("space"),
+        '\u{7f}' => Some("rubout"),
+        _ => None,
+    }
 }
 
 impl<T> Sexpr<T> {
     /// Produces an untagged (unit decorated) S-expression value from the given value.
     pub fn untag(self) -> Sexpr<()> {
+        self.map_tag(&mut |_| ())
+    }
+
+    /// Produces an S-expression value with every decorator replaced by the result of `f` on it.
+    pub fn map_tag<E, F: FnMut(T) -> E>(self, f: &mut F) -> Sexpr<E> {
         match self {
-            Sexpr::Atom(a, _) => Sexpr::Atom(a.untag(), ()),
-            Sexpr::List(l, _) => Sexpr::List(l.into_iter().map(|x| x.untag()).collect(), ()),
+            Sexpr::Atom(a, t) => Sexpr::Atom(a.map_tag(f), f(t)),
+            Sexpr::List(l, t) => Sexpr::List(l.into_iter().map(|x| x.map_tag(f)).collect(), f(t)),
+            Sexpr::Vector(v, len, t) => {
+                Sexpr::Vector(v.into_iter().map(|x| x.map_tag(f)).collect(), len, f(t))
+            }
+            Sexpr::DottedList(l, tail, t) => Sexpr::DottedList(
+                l.into_iter().map(|x| x.map_tag(f)).collect(),
+                Box::new(tail.map_tag(f)),
+                f(t),
+            ),
+            Sexpr::Hash(kind, pairs, t) => Sexpr::Hash(
+                kind,
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| (k.map_tag(f), v.map_tag(f)))
+                    .collect(),
+                f(t),
+            ),
+            Sexpr::Prefab(key, fields, t) => Sexpr::Prefab(
+                Box::new(key.map_tag(f)),
+                fields.into_iter().map(|x| x.map_tag(f)).collect(),
+                f(t),
+            ),
         }
     }
 }
@@ -98,48 +347,373 @@ impl<T> Sexpr<T> {
 impl<T> Atom<T> {
     /// Produces an untagged (unit decorated) Atom value from the given value.
     pub fn untag(self) -> Atom<()> {
+        self.map_tag(&mut |_| ())
+    }
+
+    /// Produces an Atom value with every decorator replaced by the result of `f` on it.
+    pub fn map_tag<E, F: FnMut(T) -> E>(self, f: &mut F) -> Atom<E> {
+        match self {
+            Atom::Symbol(s, t) => Atom::Symbol(s, f(t)),
+            Atom::Keyword(s, t) => Atom::Keyword(s, f(t)),
+            Atom::String(s, t) => Atom::String(s, f(t)),
+            Atom::ByteString(b, t) => Atom::ByteString(b, f(t)),
+            Atom::Regexp(s, kind, t) => Atom::Regexp(s, kind, f(t)),
+            Atom::ByteRegexp(b, kind, t) => Atom::ByteRegexp(b, kind, f(t)),
+            Atom::Char(c, t) => Atom::Char(c, f(t)),
+            Atom::Integer(i, t) => Atom::Integer(i, f(t)),
+            Atom::BigInteger(i, t) => Atom::BigInteger(i, f(t)),
+            Atom::Rational(n, d, t) => Atom::Rational(n, d, f(t)),
+            Atom::Float(fl, t) => Atom::Float(fl, f(t)),
+            Atom::Complex(re, im, t) => Atom::Complex(re, im, f(t)),
+            Atom::Boolean(b, t) => Atom::Boolean(b, f(t)),
+            Atom::Quoted(s, t) => Atom::Quoted(Box::new(s.map_tag(f)), f(t)),
+            Atom::QuasiQuoted(s, t) => Atom::QuasiQuoted(Box::new(s.map_tag(f)), f(t)),
+            Atom::Unquoted(s, t) => Atom::Unquoted(Box::new(s.map_tag(f)), f(t)),
+            Atom::UnquoteSpliced(s, t) => Atom::UnquoteSpliced(Box::new(s.map_tag(f)), f(t)),
+            Atom::SyntaxQuoted(s, t) => Atom::SyntaxQuoted(Box::new(s.map_tag(f)), f(t)),
+            Atom::QuasiSyntaxQuoted(s, t) => Atom::QuasiSyntaxQuoted(Box::new(s.map_tag(f)), f(t)),
+            Atom::Unsyntaxed(s, t) => Atom::Unsyntaxed(Box::new(s.map_tag(f)), f(t)),
+            Atom::UnsyntaxSpliced(s, t) => Atom::UnsyntaxSpliced(Box::new(s.map_tag(f)), f(t)),
+            Atom::Boxed(s, t) => Atom::Boxed(Box::new(s.map_tag(f)), f(t)),
+        }
+    }
+}
+
+/// A source text, shared by every token read out of it. The offsets of its lines are kept, so
+/// that the line and column of a token can be found without going through the whole text.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Source {
+    text: String,
+    offset: usize, // the byte offset of the text, when it's a part of a bigger input
+    first_line: usize, // the line of the bigger input that the text starts at
+    line_starts: Vec<usize>, // the byte offset of the start of every line of the text
+}
+
+impl Source {
+    pub fn new(text: &str) -> Self {
+        Self::new_at(text, 0, 1)
+    }
+
+    /// Makes the source of a part of a bigger input, which starts at the given byte offset and
+    /// line of it. The part has to start at the start of a line.
+    pub fn new_at(text: &str, offset: usize, first_line: usize) -> Self {
+        let line_starts = std::iter::once(0).chain(line_breaks(text)).collect();
+        Source {
+            text: text.to_string(),
+            offset,
+            first_line,
+            line_starts,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Gets the byte offset of the text in the whole input.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Gets the line and column of the given byte offset of the whole input, both starting at 1.
+    /// The column counts chars, not bytes.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        self.line_col_in(offset, PositionEncoding::Codepoint)
+    }
+
+    /// Like `line_col`, with the column counted in the units of the given encoding. An offset
+    /// outside of the text is taken as its nearest end, and one inside a char as its start.
+    pub fn line_col_in(&self, offset: usize, encoding: PositionEncoding) -> (usize, usize) {
+        let offset = self.clamp(offset.saturating_sub(self.offset));
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+        (
+            self.first_line + line - 1,
+            encoding.width(&self.text[line_start..offset]) + 1,
+        )
+    }
+
+    /// Gets the byte offset in the whole input of the given line and column, both starting at 1,
+    /// with the column counted in the units of the given encoding. Gives back `None` when the
+    /// line isn't in the text, the column is past the end of the line, or it's in the middle of
+    /// a char, like between the two halves of a UTF-16 surrogate pair.
+    pub fn offset_of(&self, line: usize, col: usize, encoding: PositionEncoding) -> Option<usize> {
+        let index = line.checked_sub(self.first_line)?;
+        let line_start = *self.line_starts.get(index)?;
+        let line_end = match self.line_starts.get(index + 1) {
+            Some(&next) if self.text[..next].ends_with("\r\n") => next - 2,
+            Some(&next) => next - 1,
+            None => self.text.len(),
+        };
+        let mut units = col.checked_sub(1)?;
+        for (i, c) in self.text[line_start..line_end].char_indices() {
+            if units == 0 {
+                return Some(self.offset + line_start + i);
+            }
+            units = units.checked_sub(encoding.char_width(c))?;
+        }
+        (units == 0).then_some(self.offset + line_end)
+    }
+
+    /// Moves a byte offset of the text into it, and back to the start of the char it's in.
+    fn clamp(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+}
+
+/// Gets the offsets right after each line break of the text, which can be a `\n`, a `\r\n` or a
+/// lone `\r`, like the line counting of Racket ports.
+pub(crate) fn line_breaks(text: &str) -> impl Iterator<Item = usize> + '_ {
+    let bytes = text.as_bytes();
+    bytes.iter().enumerate().filter_map(|(i, &b)| match b {
+        b'\n' => Some(i + 1),
+        b'\r' if bytes.get(i + 1) != Some(&b'\n') => Some(i + 1),
+        _ => None,
+    })
+}
+
+/// The units that columns are counted in. Editors using the Language Server Protocol and
+/// JavaScript strings count UTF-16 code units, while the columns of the errors count chars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    /// Bytes of the UTF-8 text.
+    Utf8,
+    /// UTF-16 code units, where the chars outside of the Basic Multilingual Plane, like most
+    /// emojis, count as two.
+    Utf16,
+    /// Unicode scalar values, which are Rust's chars. This is what the protocol calls UTF-32.
+    #[default]
+    Codepoint,
+}
+
+impl PositionEncoding {
+    fn char_width(self, c: char) -> usize {
         match self {
-            Atom::Symbol(s, _) => Atom::Symbol(s, ()),
-            Atom::String(s, _) => Atom::String(s, ()),
-            Atom::Integer(i, _) => Atom::Integer(i, ()),
-            Atom::Float(f, _) => Atom::Float(f, ()),
-            Atom::Boolean(b, _) => Atom::Boolean(b, ()),
-            Atom::Quoted(s, _) => Atom::Quoted(Box::new(s.untag()), ()),
-            Atom::QuasiQuoted(s, _) => Atom::QuasiQuoted(Box::new(s.untag()), ()),
-            Atom::Unquoted(s, _) => Atom::Unquoted(Box::new(s.untag()), ()),
+            PositionEncoding::Utf8 => c.len_utf8(),
+            PositionEncoding::Utf16 => c.len_utf16(),
+            PositionEncoding::Codepoint => 1,
+        }
+    }
+
+    fn width(self, s: &str) -> usize {
+        match self {
+            PositionEncoding::Utf8 => s.len(),
+            _ => s.chars().map(|c| self.char_width(c)).sum(),
         }
     }
 }
 
-/// Information on the positioning a token.
-#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+/// Information on the positioning a token. It only has the byte offsets of the token, while the
+/// text is in the source it refers to, so cloning it is cheap.
+#[derive(Clone)]
 pub struct TokInfo {
-    pub string: String,
-    pub start: (usize, usize),
-    pub end: (usize, usize),
+    source: Arc<Source>,
+    start: usize,
+    end: usize,
 }
 
-impl<'a> From<Span<'a>> for TokInfo {
-    fn from(s: Span<'a>) -> Self {
+impl TokInfo {
+    /// Makes the token of `source.text()[start..end]`. The offsets are moved into the text and
+    /// back to the start of the chars they're in, and the end to the start if it's before it.
+    pub fn new(source: Arc<Source>, start: usize, end: usize) -> Self {
+        let start = source.clamp(start);
+        let end = source.clamp(end).max(start);
+        let offset = source.offset;
         TokInfo {
-            string: s.as_str().to_string(),
-            start: s.start_pos().line_col(),
-            end: s.end_pos().line_col(),
+            source,
+            start: offset + start,
+            end: offset + end,
         }
     }
+
+    pub fn source(&self) -> &Arc<Source> {
+        &self.source
+    }
+
+    /// Gets the text of the token, as written in the source.
+    pub fn as_str(&self) -> &str {
+        let offset = self.source.offset;
+        &self.source.text[self.start - offset..self.end - offset]
+    }
+
+    /// Gets the byte offset where the token starts, in the whole input.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Gets the byte offset right after the end of the token.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn start_line_col(&self) -> (usize, usize) {
+        self.source.line_col(self.start)
+    }
+
+    pub fn end_line_col(&self) -> (usize, usize) {
+        self.source.line_col(self.end)
+    }
+
+    /// Like `start_line_col`, with the column counted in the units of the given encoding.
+    pub fn start_line_col_in(&self, encoding: PositionEncoding) -> (usize, usize) {
+        self.source.line_col_in(self.start, encoding)
+    }
+
+    pub fn end_line_col_in(&self, encoding: PositionEncoding) -> (usize, usize) {
+        self.source.line_col_in(self.end, encoding)
+    }
+}
+
+// tokens are equal when they have the same text at the same place, even if they come from two
+// different reads of the source
+impl PartialEq for TokInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.start == other.start && self.end == other.end && self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for TokInfo {}
+
+impl std::hash::Hash for TokInfo {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.start.hash(state);
+        self.end.hash(state);
+        self.as_str().hash(state);
+    }
+}
+
+impl std::fmt::Debug for TokInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokInfo")
+            .field("string", &self.as_str())
+            .field("start", &self.start_line_col())
+            .field("end", &self.end_line_col())
+            .finish()
+    }
 }
 
 impl std::fmt::Display for TokInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (start, end) = (self.start_line_col(), self.end_line_col());
         writeln!(
             f, // NOTE: "token" is deliberately lowercase
             "token starting at line {}, column {} and ending at line {}, column {}:",
-            self.start.0,
-            self.start.1,
-            self.end.0,
-            self.end.1
+            start.0,
+            start.1,
+            end.0,
+            end.1
         )?;
-        write!(f, "{}", self.string)?;
+        write!(f, "{}", self.as_str())?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod sexpr_tests {
+    use std::sync::Arc;
+
+    use super::{PositionEncoding, Source, TokInfo};
+
+    const ENCODINGS: [PositionEncoding; 3] = [
+        PositionEncoding::Utf8,
+        PositionEncoding::Utf16,
+        PositionEncoding::Codepoint,
+    ];
+
+    #[test]
+    fn test_columns_in_each_encoding() {
+        // λ is 2 bytes and 1 UTF-16 unit, 😀 is 4 bytes and 2 UTF-16 units
+        let source = Arc::new(Source::new("(λ \"😀\" x)\n\"𝄞\" y"));
+        let x = TokInfo::new(source.clone(), 11, 12);
+        assert_eq!(x.as_str(), "x");
+        let cols: Vec<_> = ENCODINGS.iter().map(|e| x.start_line_col_in(*e)).collect();
+        assert_eq!(cols, [(1, 12), (1, 9), (1, 8)]);
+        assert_eq!(x.start_line_col(), (1, 8));
+        let y = TokInfo::new(source.clone(), 21, 22);
+        assert_eq!(y.as_str(), "y");
+        let cols: Vec<_> = ENCODINGS.iter().map(|e| y.end_line_col_in(*e)).collect();
+        assert_eq!(cols, [(2, 9), (2, 7), (2, 6)]);
+    }
+
+    #[test]
+    fn test_offset_of_round_trips() {
+        let text = "a😀b\r\n  λ\n\n𝄞";
+        let source = Source::new(text);
+        for encoding in ENCODINGS {
+            for (offset, _) in text.char_indices().chain([(text.len(), ' ')]) {
+                // the middle of a `\r\n` isn't a position of a line
+                if text[..offset].ends_with('\r') && text[offset..].starts_with('\n') {
+                    continue;
+                }
+                let (line, col) = source.line_col_in(offset, encoding);
+                assert_eq!(source.offset_of(line, col, encoding), Some(offset));
+            }
+        }
+    }
+
+    #[test]
+    fn test_offset_of_invalid_positions() {
+        let source = Source::new("😀x\ny");
+        // the two halves of the emoji in UTF-16, and its bytes in UTF-8
+        assert_eq!(source.offset_of(1, 2, PositionEncoding::Utf16), None);
+        assert_eq!(source.offset_of(1, 3, PositionEncoding::Utf16), Some(4));
+        assert_eq!(source.offset_of(1, 3, PositionEncoding::Utf8), None);
+        assert_eq!(source.offset_of(1, 5, PositionEncoding::Utf8), Some(4));
+        // the end of a line is a position, but not past it
+        assert_eq!(source.offset_of(1, 3, PositionEncoding::Codepoint), Some(5));
+        assert_eq!(source.offset_of(1, 4, PositionEncoding::Codepoint), None);
+        assert_eq!(source.offset_of(2, 2, PositionEncoding::Codepoint), Some(7));
+        assert_eq!(source.offset_of(3, 1, PositionEncoding::Codepoint), None);
+        assert_eq!(source.offset_of(0, 1, PositionEncoding::Codepoint), None);
+        assert_eq!(source.offset_of(1, 0, PositionEncoding::Codepoint), None);
+    }
+
+    #[test]
+    fn test_part_of_a_bigger_input() {
+        let source = Source::new_at("𝄞 z", 10, 3);
+        let z = TokInfo::new(Arc::new(source), 5, 6);
+        assert_eq!(z.start(), 15);
+        assert_eq!(z.start_line_col_in(PositionEncoding::Utf16), (3, 4));
+        assert_eq!(
+            z.source().offset_of(3, 4, PositionEncoding::Utf16),
+            Some(15)
+        );
+    }
+
+    #[test]
+    fn test_line_endings() {
+        // the line breaks of Unix, Windows and classic Mac OS, and a file without a final one
+        let text = "a\nb\r\nc\rd";
+        let source = Source::new(text);
+        for (name, line) in [("a", 1), ("b", 2), ("c", 3), ("d", 4)] {
+            let offset = text.find(name).unwrap();
+            assert_eq!(source.line_col(offset), (line, 1));
+            let encoding = PositionEncoding::Codepoint;
+            assert_eq!(source.offset_of(line, 1, encoding), Some(offset));
+            assert_eq!(source.offset_of(line, 2, encoding), Some(offset + 1));
+        }
+        assert_eq!(source.offset_of(5, 1, PositionEncoding::Codepoint), None);
+        // the end of a line is before all of its line break
+        assert_eq!(source.offset_of(2, 2, PositionEncoding::Codepoint), Some(3));
+        assert_eq!(source.offset_of(2, 3, PositionEncoding::Codepoint), None);
+    }
+
+    #[test]
+    fn test_offsets_outside_of_the_text() {
+        let source = Arc::new(Source::new_at(
+            "a
+λ", 10, 3,
+        ));
+        assert_eq!(source.line_col(0), (3, 1));
+        assert_eq!(source.line_col(13), (4, 1));
+        assert_eq!(source.line_col(100), (4, 2));
+        let tok = TokInfo::new(source.clone(), 3, 100);
+        assert_eq!((tok.start(), tok.end(), tok.as_str()), (12, 14, "λ"));
+        let tok = TokInfo::new(source, 3, 1);
+        assert_eq!((tok.start(), tok.end(), tok.as_str()), (12, 12, ""));
+    }
+}
@@ -1,28 +1,163 @@
-use pest::{iterators::Pair, Parser};
+use std::sync::Arc;
+
+use num_bigint::BigInt;
+use pest::{
+    iterators::{Pair, Pairs},
+    Parser, Span,
+};
 use pest_derive::Parser;
 
 use crate::{
-    errors::ParsingError,
-    sexpr::TokInfo,
-    sexpr::{Atom, Sexpr},
+    brackets,
+    depth::check_depth,
+    errors::{Expected, ParsingError},
+    number::{self, Exactness, Number, Real},
+    sexpr::{Atom, HashKind, RegexpKind, Sexpr, Source, TokInfo},
 };
 
+/// The elements of a list, with the dots in between them (see `SexprParser::parse_elements`).
+pub(crate) type Elements = (Vec<Sexpr<TokInfo>>, Vec<(usize, TokInfo)>);
+
+/// A part of a string, which is raw text or an escape like `\n`, starting at `start` in its
+/// source. An escape has the rule and the text of what follows its backslash.
+pub(crate) struct StringPart<'a> {
+    pub(crate) start: usize,
+    pub(crate) text: &'a str,
+    pub(crate) escape: Option<(Rule, &'a str)>,
+}
+
 #[derive(Parser)]
 #[grammar = "./sexpr.pest"]
 /// Sexpr parser with extended atom variants and comments.
 pub struct SexprParser;
 
+/// Options that change how the reader reads its input. Most of them are the parameters of the
+/// Racket reader that a DrRacket header can set, see `hashlang::strip_header`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReaderOptions {
+    /// Rejects the non-Racket spellings that used to be accepted, like the numbers 0xFF, 20f and
+    /// 1_000, or the string escape `\u{a}`.
+    pub strict: bool,
+    /// When false, the names of symbols and keywords are folded to lowercase, except for the
+    /// parts between pipes and the escaped chars, like `read-case-sensitive`.
+    pub case_sensitive: bool,
+    /// Whether `[` and `]` can be used as brackets, like `read-square-bracket-as-paren`.
+    pub square_brackets: bool,
+    /// Whether `{` and `}` can be used as brackets, like `read-curly-brace-as-paren`.
+    pub curly_brackets: bool,
+    /// Whether the booleans can be written `#t` and `#f`.
+    pub short_booleans: bool,
+    /// Whether the booleans can be written `#true` and `#false`.
+    pub long_booleans: bool,
+    /// Reads the numbers with a decimal point or an exponent as exact, like 0.1 as 1/10, as the
+    /// teaching languages do. It's the opposite of `read-decimal-as-inexact`.
+    pub decimal_as_exact: bool,
+    /// How deep the forms can be nested, where a top-level atom has a depth of 1, and each list,
+    /// vector or prefix like `'` around it adds 1. Reading deeper forms would need more stack
+    /// than a thread is likely to have, so they're reported as `TooDeep` instead, before the
+    /// grammar reads them. Block comments can't be nested deeper either.
+    pub max_depth: usize,
+    /// How many forms can be read, counting the nested ones, so `(a 'b)` counts as 4.
+    pub max_forms: usize,
+    /// How long the input can be, in bytes.
+    pub max_size: usize,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        ReaderOptions {
+            strict: false,
+            case_sensitive: true,
+            square_brackets: true,
+            curly_brackets: true,
+            short_booleans: true,
+            long_booleans: true,
+            decimal_as_exact: false,
+            max_depth: 256,
+            max_forms: 1 << 20,
+            max_size: 16 << 20,
+        }
+    }
+}
+
+impl ReaderOptions {
+    /// Checks that an input of the given length isn't longer than `max_size`.
+    pub(crate) fn check_size(&self, len: usize) -> Result<(), ParsingError> {
+        if len > self.max_size {
+            return Err(ParsingError::TooLarge(len));
+        }
+        Ok(())
+    }
+}
+
+/// What a read needs to make the tokens of what it reads: the options, and the source that the
+/// spans are in. The spans of pest are relative to the text given to it, which starts at `base`.
+struct Reader<'a> {
+    opts: &'a ReaderOptions,
+    source: Arc<Source>,
+    base: usize,
+}
+
+impl Reader<'_> {
+    fn tok(&self, span: Span) -> TokInfo {
+        TokInfo::new(
+            self.source.clone(),
+            self.base + span.start(),
+            self.base + span.end(),
+        )
+    }
+}
+
 impl SexprParser {
     pub fn parse_all(input: &str) -> Result<Vec<Sexpr<TokInfo>>, ParsingError> {
-        let parsed = Self::parse(Rule::program, input)?
-            .next()
-            .unwrap()
-            .into_inner();
+        Self::parse_all_with(input, &ReaderOptions::default())
+    }
+
+    pub fn parse_all_with(
+        input: &str,
+        opts: &ReaderOptions,
+    ) -> Result<Vec<Sexpr<TokInfo>>, ParsingError> {
+        Self::parse_source(Arc::new(Source::new(input)), opts)
+    }
+
+    /// Parses every form of the given source, which can be a part of a bigger input.
+    pub(crate) fn parse_source(
+        source: Arc<Source>,
+        opts: &ReaderOptions,
+    ) -> Result<Vec<Sexpr<TokInfo>>, ParsingError> {
+        let len = source.text().len();
+        Self::parse_range(&source, 0, len, opts)
+    }
+
+    /// Parses every form of `source.text()[start..end]`, as if the rest of the text wasn't there.
+    pub(crate) fn parse_range(
+        source: &Arc<Source>,
+        start: usize,
+        end: usize,
+        opts: &ReaderOptions,
+    ) -> Result<Vec<Sexpr<TokInfo>>, ParsingError> {
+        let (parsed, base) = Self::parse_program(source, start, end, opts)?;
+        Self::read_program(parsed, source, base, opts)
+    }
+
+    /// Reads the forms of the pairs that `parse_program` gave for `source`.
+    pub(crate) fn read_program(
+        parsed: Pairs<Rule>,
+        source: &Arc<Source>,
+        base: usize,
+        opts: &ReaderOptions,
+    ) -> Result<Vec<Sexpr<TokInfo>>, ParsingError> {
+        let parsed = parsed.into_iter().next().unwrap().into_inner();
+        let cx = Reader {
+            opts,
+            source: source.clone(),
+            base,
+        };
         let mut result = Vec::new();
         for p in parsed {
-            let rule = p.clone().as_rule();
+            let rule = p.as_rule();
             if rule != Rule::EOI {
-                let x = Self::parse_sexpr(p);
+                let x = Self::parse_sexpr(p, &cx);
                 if matches!(x, Err(ParsingError::NothingToParse)) {
                     continue;
                 }
@@ -32,145 +167,907 @@ impl SexprParser {
         Ok(result)
     }
 
-    pub fn parse_str(input: &str) -> Result<Sexpr<TokInfo>, ParsingError> {
-        let mut parsed = Self::parse(Rule::sexpr, input)?;
-        Self::parse_sexpr(parsed.next().unwrap())
+    /// Reads `source.text()[start..end]` with the grammar, checking that it's within the limits
+    /// of the options, so that the pairs can be walked with recursion. Also gives back the offset
+    /// that the spans of the pairs are relative to, which is past the byte order mark if there
+    /// is one.
+    pub(crate) fn parse_program<'a>(
+        source: &'a Arc<Source>,
+        start: usize,
+        end: usize,
+        opts: &ReaderOptions,
+    ) -> Result<(Pairs<'a, Rule>, usize), ParsingError> {
+        opts.check_size(end - start)?;
+        let start = start + bom_len(source, start, end);
+        check_depth(source, start, end, Rule::program, opts)?;
+        let parsed = Self::parse(Rule::program, &source.text()[start..end])
+            .map_err(|e| grammar_error(e, source, start, end))?;
+        let cx = Reader {
+            opts,
+            source: source.clone(),
+            base: start,
+        };
+        Self::check_forms(&parsed, &cx, &mut 0)?;
+        Ok((parsed, start))
     }
 
-    fn parse_atom(atom: Pair<Rule>) -> Result<Atom<TokInfo>, ParsingError> {
-        let inner = atom.into_inner().next().unwrap();
-        match inner.as_rule() {
-            Rule::bool => {
-                let inner = inner.into_inner().next().unwrap();
-                match inner.as_rule() {
-                    Rule::bool_false => Ok(Atom::Boolean(false, inner.as_span().into())),
-                    Rule::bool_true => Ok(Atom::Boolean(true, inner.as_span().into())),
-                    _ => Err(ParsingError::InvalidSyntax(
-                        inner.as_span().into(),
-                        inner.to_string(),
-                        Some(
-                            "The given token was matched as a boolean, but wasn't a valid boolean"
-                                .to_string(),
-                        ),
-                    )),
+    /// Parses every top-level form of the input, recovering from errors instead of giving up at
+    /// the first one. Returns the forms that could be parsed, along with every error found.
+    pub fn parse_all_recovering(input: &str) -> (Vec<Sexpr<TokInfo>>, Vec<ParsingError>) {
+        Self::parse_all_recovering_with(input, &ReaderOptions::default())
+    }
+
+    /// Like `parse_all_recovering`, with the given options. Each top-level form is parsed on its
+    /// own, and when one can't be parsed the reader skips to the next line that looks like the
+    /// start of a form, meaning that it starts with something other than whitespace, a comment or
+    /// a closing bracket. Reading stops at the first form past `max_forms`.
+    pub fn parse_all_recovering_with(
+        input: &str,
+        opts: &ReaderOptions,
+    ) -> (Vec<Sexpr<TokInfo>>, Vec<ParsingError>) {
+        let source = Arc::new(Source::new(input));
+        Self::parse_range_recovering(&source, 0, input.len(), opts)
+    }
+
+    /// Like `parse_all_recovering_with`, for `source.text()[start..end]`.
+    pub(crate) fn parse_range_recovering(
+        source: &Arc<Source>,
+        start: usize,
+        end: usize,
+        opts: &ReaderOptions,
+    ) -> (Vec<Sexpr<TokInfo>>, Vec<ParsingError>) {
+        if let Err(e) = opts.check_size(end - start) {
+            return (Vec::new(), vec![e]);
+        }
+        let input = &source.text()[..end];
+        let mut forms = Vec::new();
+        let mut errors = Vec::new();
+        let mut count = 0;
+        let mut offset = start + bom_len(source, start, end);
+        let deep = |rule, start| check_depth(source, start, end, rule, opts);
+        while offset < input.len() {
+            if let Err(e) = deep(Rule::leading_trivia, offset) {
+                // the next form can't start before the comment that is nested too deep
+                let from = match &e {
+                    ParsingError::TooDeep(tok) => tok.start(),
+                    _ => offset,
+                };
+                offset = next_form_start(input, from);
+                errors.push(e);
+                continue;
+            }
+            // the trivia rule can't fail, as it can match nothing
+            let trivia = Self::parse(Rule::leading_trivia, &input[offset..]).unwrap();
+            let start = offset + trivia.as_str().len();
+            if start >= input.len() {
+                break;
+            }
+            if let Err(e) = deep(Rule::sexpr, start) {
+                errors.push(e);
+                offset = next_form_start(input, start);
+                continue;
+            }
+            let rest = &input[start..];
+            match Self::parse(Rule::sexpr, rest) {
+                Ok(mut pairs) => {
+                    let cx = Reader {
+                        opts,
+                        source: source.clone(),
+                        base: start,
+                    };
+                    let checked = Self::check_forms(&pairs, &cx, &mut count);
+                    let pair = pairs.next().unwrap();
+                    offset = start + pair.as_span().end();
+                    match checked.and_then(|_| Self::parse_sexpr(pair, &cx)) {
+                        Ok(sexpr) => forms.push(sexpr),
+                        Err(ParsingError::NothingToParse) => {}
+                        Err(e @ ParsingError::TooManyForms(_)) => {
+                            errors.push(e);
+                            break;
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                }
+                Err(e) => {
+                    offset = next_form_start(input, start);
+                    errors.push(grammar_error(e, source, start, offset));
                 }
             }
-            Rule::symbol => Ok(Atom::Symbol(
-                inner.as_str().to_string(),
-                inner.as_span().into(),
-            )),
-            Rule::string => {
-                let mut string = inner.as_str().to_string();
-                // trimming the quotes
-                string.remove(0);
-                string.remove(string.len() - 1);
-                Ok(Atom::String(string, inner.as_span().into()))
-            }
-            Rule::float => {
-                let mut string = inner.as_str().to_string();
-                if string.ends_with('f') {
-                    string.remove(string.len() - 1);
+        }
+        (forms, errors)
+    }
+
+    /// Reads the atom of the given token again, from where it starts in its source, so that
+    /// what comes after it is read like it was, as it can change what the atom is.
+    pub(crate) fn read_atom(
+        tok: &TokInfo,
+        opts: &ReaderOptions,
+    ) -> Result<Sexpr<TokInfo>, ParsingError> {
+        let source = tok.source();
+        let start = tok.start() - source.offset();
+        check_depth(source, start, source.text().len(), Rule::sexpr, opts)?;
+        let mut pairs = Self::parse(Rule::sexpr, &source.text()[start..])
+            .map_err(|e| grammar_error(e, source, start, source.text().len()))?;
+        let cx = Reader {
+            opts,
+            source: source.clone(),
+            base: start,
+        };
+        Self::parse_sexpr(pairs.next().unwrap(), &cx)
+    }
+
+    pub fn parse_str(input: &str) -> Result<Sexpr<TokInfo>, ParsingError> {
+        Self::parse_str_with(input, &ReaderOptions::default())
+    }
+
+    pub fn parse_str_with(
+        input: &str,
+        opts: &ReaderOptions,
+    ) -> Result<Sexpr<TokInfo>, ParsingError> {
+        opts.check_size(input.len())?;
+        let source = Arc::new(Source::new(input));
+        check_depth(&source, 0, input.len(), Rule::sexpr, opts)?;
+        let mut parsed = Self::parse(Rule::sexpr, input)
+            .map_err(|e| grammar_error(e, &source, 0, input.len()))?;
+        let cx = Reader {
+            opts,
+            source,
+            base: 0,
+        };
+        Self::check_forms(&parsed, &cx, &mut 0)?;
+        Self::parse_sexpr(parsed.next().unwrap(), &cx)
+    }
+
+    /// Counts the forms read by the grammar, along with the `count` read before them, checking
+    /// that there are no more than `max_forms`, before they're turned into `Sexpr`s. How deep
+    /// they're nested was checked before the grammar read them, see `check_depth`.
+    fn check_forms(
+        pairs: &Pairs<Rule>,
+        cx: &Reader,
+        count: &mut usize,
+    ) -> Result<(), ParsingError> {
+        for pair in pairs.clone().flatten() {
+            if pair.as_rule() == Rule::sexpr {
+                *count += 1;
+                if *count > cx.opts.max_forms {
+                    return Err(ParsingError::TooManyForms(cx.tok(pair.as_span())));
                 }
-                Ok(Atom::Float(
-                    string.parse::<f64>().unwrap(),
-                    inner.as_span().into(),
-                ))
             }
-            Rule::quoted => {
-                let inner = inner.into_inner().next().unwrap();
+        }
+        Ok(())
+    }
+
+    /// Parses an atom, given the rule inside of it. The quoted forms are parsed by `parse_quoted`.
+    fn parse_atom(inner: Pair<Rule>, cx: &Reader) -> Result<Atom<TokInfo>, ParsingError> {
+        match inner.as_rule() {
+            Rule::bool => Self::boolean(cx.tok(inner.as_span()), cx.opts),
+            Rule::symbol => {
                 let span = inner.as_span();
-                Ok(Atom::Quoted(
-                    Box::new(Self::parse_sexpr(inner)?),
-                    span.into(),
-                ))
+                let name = Self::symbol_name(Self::symbol_parts(inner), cx.opts);
+                Ok(Atom::Symbol(name, cx.tok(span)))
             }
-            Rule::quasiquoted => {
-                let inner = inner.into_inner().next().unwrap();
+            Rule::keyword => {
+                let span = inner.as_span();
+                let symbol = inner.into_inner().next().unwrap();
+                let name = Self::symbol_name(Self::symbol_parts(symbol), cx.opts);
+                Ok(Atom::Keyword(name, cx.tok(span)))
+            }
+            Rule::string => {
                 let span = inner.as_span();
-                Ok(Atom::QuasiQuoted(
-                    Box::new(Self::parse_sexpr(inner)?),
-                    span.into(),
+                let parts = Self::string_parts(inner, cx);
+                Ok(Atom::String(
+                    Self::decode_string(parts, cx.opts, &cx.source)?,
+                    cx.tok(span),
                 ))
             }
-            Rule::unquoted => {
-                let inner = inner.into_inner().next().unwrap();
+            Rule::here_string => {
+                let span = inner.as_span();
+                let body = inner.into_inner().find(|p| p.as_rule() == Rule::here_body);
+                let body = body.map_or("", |b| b.as_str()).to_string();
+                Ok(Atom::String(body, cx.tok(span)))
+            }
+            Rule::byte_string => {
                 let span = inner.as_span();
-                Ok(Atom::Unquoted(
-                    Box::new(Self::parse_sexpr(inner)?),
-                    span.into(),
+                let parts = Self::string_parts(inner, cx);
+                Ok(Atom::ByteString(
+                    Self::decode_byte_string(parts, &cx.source)?,
+                    cx.tok(span),
                 ))
             }
-            Rule::integer => {
-                let inner = inner.into_inner().next().unwrap();
-                match inner.as_rule() {
-                    Rule::dec_integer => Ok(Atom::Integer(
-                        inner.as_str().parse().unwrap(),
-                        inner.as_span().into(),
+            Rule::regexp => {
+                let span = inner.as_span();
+                let mut parts = inner.into_inner();
+                let kind = match parts.next().unwrap().as_str() {
+                    "#px" => RegexpKind::Px,
+                    _ => RegexpKind::Rx,
+                };
+                let pattern = parts.next().unwrap();
+                let is_bytes = pattern.as_rule() == Rule::byte_string;
+                let parts = Self::string_parts(pattern, cx);
+                match is_bytes {
+                    true => Ok(Atom::ByteRegexp(
+                        Self::decode_byte_string(parts, &cx.source)?,
+                        kind,
+                        cx.tok(span),
                     )),
-                    Rule::hex_integer => {
-                        let mut string = inner.as_str().to_string();
-                        // trim "0x"
-                        string.remove(0);
-                        string.remove(0);
-                        Ok(Atom::Integer(
-                            i64::from_str_radix(&string, 16).unwrap(),
-                            inner.as_span().into(),
-                        ))
-                    }
-                    _ => Err(ParsingError::InvalidSyntax(
-                        inner.as_span().into(),
-                        inner.to_string(),
-                        Some(
-                            "The given token was matched as an integer, but wasn't a valid integer"
-                                .to_string(),
-                        ),
+                    false => Ok(Atom::Regexp(
+                        Self::decode_string(parts, cx.opts, &cx.source)?,
+                        kind,
+                        cx.tok(span),
                     )),
                 }
             }
+            Rule::character => {
+                let tok = cx.tok(inner.as_span());
+                let inner = inner.into_inner().next().unwrap();
+                Self::character(inner.as_rule(), inner.as_str(), tok)
+            }
+            Rule::number => Self::parse_number(inner, cx),
+            Rule::legacy_number => Self::parse_legacy_number(inner, cx),
             Rule::sexpr_comment => Err(ParsingError::NothingToParse),
             _ => Err(ParsingError::InvalidSyntax(
-                inner.as_span().into(),
+                cx.tok(inner.as_span()),
                 inner.to_string(),
                 Some("The given token was matched as an atom, but wasn't a valid atom".to_string()),
             )),
         }
     }
 
-    fn pair_is_sexprcomment(pair: Pair<Rule>) -> bool {
-        pair.into_inner()
-            .next()
-            .unwrap()
+    /// Parses a form behind one of the reader abbreviations, like `'x` or `#&x`. It's kept apart
+    /// from `parse_atom`, as it recurses, and the frames of the recursion should stay small.
+    fn parse_quoted(quoted: Pair<Rule>, cx: &Reader) -> Result<Atom<TokInfo>, ParsingError> {
+        let rule = quoted.as_rule();
+        let inner = quoted.into_inner().next().unwrap();
+        let span = inner.as_span();
+        let sexpr = Self::parse_sexpr(inner, cx)?;
+        Ok(Self::abbreviation(rule, sexpr, cx.tok(span)))
+    }
+
+    /// Makes the atom of a prefix like `'` or `#&`, given by the rule it's read with, and the
+    /// form after it, whose token is `tok`.
+    pub(crate) fn abbreviation(rule: Rule, sexpr: Sexpr<TokInfo>, tok: TokInfo) -> Atom<TokInfo> {
+        let sexpr = Box::new(sexpr);
+        match rule {
+            Rule::quoted => Atom::Quoted(sexpr, tok),
+            Rule::quasiquoted => Atom::QuasiQuoted(sexpr, tok),
+            Rule::unquoted => Atom::Unquoted(sexpr, tok),
+            Rule::unquote_spliced => Atom::UnquoteSpliced(sexpr, tok),
+            Rule::syntax_quoted => Atom::SyntaxQuoted(sexpr, tok),
+            Rule::quasisyntax_quoted => Atom::QuasiSyntaxQuoted(sexpr, tok),
+            Rule::unsyntaxed => Atom::Unsyntaxed(sexpr, tok),
+            Rule::boxed => Atom::Boxed(sexpr, tok),
+            _ => Atom::UnsyntaxSpliced(sexpr, tok),
+        }
+    }
+
+    /// Gets the parts of a symbol, with the rule each one was read with.
+    fn symbol_parts(symbol: Pair<'_, Rule>) -> impl Iterator<Item = (Rule, &str)> {
+        symbol
             .into_inner()
-            .next()
-            .unwrap()
-            .as_rule()
-            == Rule::sexpr_comment
+            .map(|part| (part.as_rule(), part.as_str()))
+    }
+
+    /// Gets the real name of a symbol made of the given parts, where the parts quoted between
+    /// pipes and the escaped chars are taken literally, while the rest is folded to lowercase if
+    /// the reader isn't case sensitive.
+    pub(crate) fn symbol_name<'a>(
+        parts: impl IntoIterator<Item = (Rule, &'a str)>,
+        opts: &ReaderOptions,
+    ) -> String {
+        parts
+            .into_iter()
+            .map(|(rule, s)| match rule {
+                Rule::symbol_pipe => s[1..s.len() - 1].to_string(),
+                Rule::symbol_escape => s[1..].to_string(),
+                _ if !opts.case_sensitive => s.to_lowercase(),
+                _ => s.to_string(),
+            })
+            .collect()
+    }
+
+    /// Checks if the given name is read back as a symbol with that name without quoting it, so
+    /// that it isn't read as a number, a boolean or anything else.
+    pub(crate) fn is_plain_symbol(name: &str) -> bool {
+        // a name like ''''a is read as nested forms, which could be too deep for the grammar
+        let source = Arc::new(Source::new(name));
+        let opts = ReaderOptions::default();
+        if check_depth(&source, 0, name.len(), Rule::atom, &opts).is_err() {
+            return false;
+        }
+        let atom = match Self::parse(Rule::atom, name).map(|mut p| p.next()) {
+            // a lone dot is special inside of lists
+            Ok(Some(atom)) if atom.as_str().len() == name.len() && name != "." => atom,
+            _ => return false,
+        };
+        match atom.into_inner().next() {
+            Some(symbol) if symbol.as_rule() == Rule::symbol => symbol
+                .into_inner()
+                .all(|part| part.as_rule() == Rule::symbol_plain),
+            _ => false,
+        }
+    }
+
+    /// Makes the boolean written as the given token, like `#t` or `#false`, if the options allow
+    /// it to be written that way.
+    pub(crate) fn boolean(
+        tok: TokInfo,
+        opts: &ReaderOptions,
+    ) -> Result<Atom<TokInfo>, ParsingError> {
+        let text = tok.as_str();
+        let allowed = match text.len() {
+            2 => opts.short_booleans,
+            _ => opts.long_booleans,
+        };
+        if !allowed {
+            return Err(ParsingError::InvalidSyntax(
+                tok.clone(),
+                text.to_string(),
+                Some(format!(
+                    "The boolean can't be written `{}` in this language",
+                    text
+                )),
+            ));
+        }
+        Ok(Atom::Boolean(text.starts_with("#t"), tok))
     }
 
-    fn parse_sexpr(sexp: Pair<Rule>) -> Result<Sexpr<TokInfo>, ParsingError> {
+    /// Makes the char of a character constant like `#\a`, given the rule and the text of what
+    /// follows the `#\`, and the token of the whole constant.
+    pub(crate) fn character(
+        rule: Rule,
+        text: &str,
+        tok: TokInfo,
+    ) -> Result<Atom<TokInfo>, ParsingError> {
+        let c = match rule {
+            Rule::char_named => match text.to_lowercase().as_str() {
+                "nul" | "null" => Some('\0'),
+                "backspace" => Some('\u{8}'),
+                "tab" => Some('\t'),
+                "newline" | "linefeed" => Some('\n'),
+                "vtab" => Some('\u{b}'),
+                "page" => Some('\u{c}'),
+                "return" => Some('\r'),
+                "space" => Some(' '),
+                "rubout" | "delete" => Some('\u{7f}'),
+                _ => None,
+            },
+            Rule::char_octal => u32::from_str_radix(text, 8).ok().and_then(char::from_u32),
+            Rule::char_unicode => u32::from_str_radix(&text[1..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            Rule::char_any => text.chars().next(),
+            _ => None,
+        };
+        match c {
+            Some(c) => Ok(Atom::Char(c, tok)),
+            None => Err(ParsingError::InvalidSyntax(
+                tok.clone(),
+                tok.as_str().to_string(),
+                Some("The given token is not a valid character constant".to_string()),
+            )),
+        }
+    }
+
+    /// Gets the code of an escape, given the rule and the text of what follows its backslash.
+    /// Line continuations have none.
+    fn escape_code(rule: Rule, esc: &str) -> Option<u32> {
+        match rule {
+            Rule::predefined => Some(match esc {
+                "a" => 0x7,
+                "b" => 0x8,
+                "t" => 0x9,
+                "n" => 0xA,
+                "v" => 0xB,
+                "f" => 0xC,
+                "r" => 0xD,
+                "e" => 0x1B,
+                other => other.chars().next().map_or(0, |c| c as u32),
+            }),
+            Rule::octal => u32::from_str_radix(esc, 8).ok(),
+            Rule::byte | Rule::unicode => u32::from_str_radix(&esc[1..], 16).ok(),
+            Rule::legacy_unicode => {
+                let hex = esc.trim_start_matches("u{").trim_end_matches('}');
+                u32::from_str_radix(hex, 16).ok()
+            }
+            _ => None,
+        }
+    }
+
+    fn invalid_escape(escape: &StringPart, msg: &str, source: &Arc<Source>) -> ParsingError {
+        let end = escape.start + escape.text.len();
+        ParsingError::InvalidSyntax(
+            TokInfo::new(source.clone(), escape.start, end),
+            escape.text.to_string(),
+            Some(msg.to_string()),
+        )
+    }
+
+    /// Gets the parts of a string or a byte string read by the grammar.
+    fn string_parts<'a>(string: Pair<'a, Rule>, cx: &Reader) -> Vec<StringPart<'a>> {
+        string
+            .into_inner()
+            .map(|part| StringPart {
+                start: cx.base + part.as_span().start(),
+                text: part.as_str(),
+                // raw strings have no inner pairs
+                escape: part
+                    .into_inner()
+                    .next()
+                    .map(|esc| (esc.as_rule(), esc.as_str())),
+            })
+            .collect()
+    }
+
+    /// Decodes the contents of a string, given its parts, with their escapes. The original lexeme
+    /// is still kept in the `TokInfo` of the string.
+    pub(crate) fn decode_string(
+        parts: Vec<StringPart>,
+        opts: &ReaderOptions,
+        source: &Arc<Source>,
+    ) -> Result<String, ParsingError> {
+        let mut decoded = String::new();
+        // a high surrogate escape, waiting for the low surrogate escape that should follow it
+        let mut high_surrogate: Option<(u32, &StringPart)> = None;
+        for part in &parts {
+            let code = match part.escape {
+                None => {
+                    decoded.push_str(part.text);
+                    None
+                }
+                Some((Rule::legacy_unicode, _)) if opts.strict => {
+                    return Err(Self::invalid_escape(
+                        part,
+                        "This is not a valid Racket escape",
+                        source,
+                    ));
+                }
+                Some((rule, esc)) => Self::escape_code(rule, esc),
+            };
+
+            let c = match (high_surrogate.take(), code) {
+                (Some((high, _)), Some(low @ 0xDC00..=0xDFFF)) => {
+                    char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+                }
+                (Some((_, high_escape)), _) => {
+                    return Err(Self::invalid_escape(
+                        high_escape,
+                        "A high surrogate has to be followed by a low one",
+                        source,
+                    ));
+                }
+                (None, Some(high @ 0xD800..=0xDBFF)) => {
+                    high_surrogate = Some((high, part));
+                    continue;
+                }
+                (None, Some(code)) => match char::from_u32(code) {
+                    Some(c) => Some(c),
+                    None => {
+                        return Err(Self::invalid_escape(
+                            part,
+                            "The escape is not a valid unicode char",
+                            source,
+                        ))
+                    }
+                },
+                (None, None) => None,
+            };
+            decoded.extend(c);
+        }
+        if let Some((_, high_escape)) = high_surrogate {
+            return Err(Self::invalid_escape(
+                high_escape,
+                "A high surrogate has to be followed by a low one",
+                source,
+            ));
+        }
+        Ok(decoded)
+    }
+
+    /// Decodes the contents of a byte string, given its parts, with their escapes. Every char has
+    /// to fit in a byte.
+    pub(crate) fn decode_byte_string(
+        parts: Vec<StringPart>,
+        source: &Arc<Source>,
+    ) -> Result<Vec<u8>, ParsingError> {
+        let mut decoded = Vec::new();
+        for part in &parts {
+            let codes = match part.escape {
+                None => part.text.chars().map(|c| c as u32).collect(),
+                Some((Rule::legacy_unicode | Rule::unicode, _)) => {
+                    return Err(Self::invalid_escape(
+                        part,
+                        "Unicode escapes are not allowed in byte strings",
+                        source,
+                    ))
+                }
+                Some((rule, esc)) => Self::escape_code(rule, esc).into_iter().collect::<Vec<_>>(),
+            };
+            for code in codes {
+                match u8::try_from(code) {
+                    Ok(byte) => decoded.push(byte),
+                    Err(_) => {
+                        return Err(Self::invalid_escape(
+                            part,
+                            "A byte string can only contain chars that fit in a byte",
+                            source,
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(decoded)
+    }
+
+    fn parse_number(number: Pair<Rule>, cx: &Reader) -> Result<Atom<TokInfo>, ParsingError> {
+        let tok = cx.tok(number.as_span());
+        let mut exactness = None;
+        let mut parts = Vec::new();
+        let mut radix = 10;
+        for part in number.into_inner() {
+            match part.as_rule() {
+                Rule::exactness => exactness = Some(part.as_str()),
+                Rule::complex2 | Rule::complex8 | Rule::complex10 | Rule::complex16 => {
+                    radix = match part.as_rule() {
+                        Rule::complex2 => 2,
+                        Rule::complex8 => 8,
+                        Rule::complex16 => 16,
+                        _ => 10,
+                    };
+                    let is_imag = |p: &Pair<Rule>| {
+                        matches!(
+                            p.as_rule(),
+                            Rule::imag2 | Rule::imag8 | Rule::imag10 | Rule::imag16
+                        )
+                    };
+                    parts = part
+                        .into_inner()
+                        .map(|p| (p.as_str(), is_imag(&p)))
+                        .collect();
+                }
+                // the radix prefix is already implied by the complex rule
+                _ => {}
+            }
+        }
+        Self::number(&parts, radix, exactness, tok, cx.opts)
+    }
+
+    /// Makes the number of the given token, given its radix, its exactness prefix like `#e` if
+    /// it has one, and the parts of its complex rule, with whether each one is imaginary. Those
+    /// are a real or an imaginary part, or two parts, like `1` and `+2i`, or `1` and `2` in `1@2`.
+    pub(crate) fn number(
+        parts: &[(&str, bool)],
+        radix: u32,
+        exactness: Option<&str>,
+        tok: TokInfo,
+        opts: &ReaderOptions,
+    ) -> Result<Atom<TokInfo>, ParsingError> {
+        let exactness = exactness.map(|prefix| match prefix.eq_ignore_ascii_case("#e") {
+            true => Exactness::Exact,
+            false => Exactness::Inexact,
+        });
+        // without a prefix, decimals can be read as exact, but not infinities, NaNs, or numbers
+        // with `#` digits, which stay inexact
+        let exactness_of = |text: &str| match exactness {
+            None if opts.decimal_as_exact => {
+                let lower = text.to_ascii_lowercase();
+                let inexact = ["inf.", "nan.", "#"].iter().any(|s| lower.contains(s));
+                (!inexact).then_some(Exactness::Exact)
+            }
+            exactness => exactness,
+        };
+        let real = |text: &str| number::read_real(text, radix, exactness_of(text));
+        let imag = |text: &str| number::read_imag(text, radix, exactness_of(text));
+        let read = match *parts {
+            [(first, _), (second, true)] => {
+                real(first).and_then(|re| Ok(Number::rectangular(re, imag(second)?)))
+            }
+            [(first, _), (second, false)] => {
+                real(first).and_then(|mag| Ok(Number::polar(mag, real(second)?)))
+            }
+            [(first, true)] => imag(first).map(|im| Number::rectangular(Real::Integer(0), im)),
+            [(first, false)] => real(first).map(Number::Real),
+            _ => Err("The number has no parts".to_string()),
+        };
+
+        match read {
+            Ok(number) => Ok(Self::number_atom(number, tok)),
+            Err(msg) => {
+                let found = tok.as_str().to_string();
+                Err(ParsingError::InvalidSyntax(tok, found, Some(msg)))
+            }
+        }
+    }
+
+    fn number_atom(number: Number, tok: TokInfo) -> Atom<TokInfo> {
+        match number {
+            Number::Real(Real::Integer(i)) => Atom::Integer(i, tok),
+            Number::Real(Real::BigInteger(i)) => Atom::BigInteger(i, tok),
+            Number::Real(Real::Rational(n, d)) => Atom::Rational(n, d, tok),
+            Number::Real(Real::Float(f)) => Atom::Float(f, tok),
+            Number::Complex(re, im) => Atom::Complex(re, im, tok),
+        }
+    }
+
+    fn parse_legacy_number(number: Pair<Rule>, cx: &Reader) -> Result<Atom<TokInfo>, ParsingError> {
+        let tok = cx.tok(number.as_span());
+        let rule = number.into_inner().next().unwrap().as_rule();
+        Self::legacy_number(rule, tok, cx.opts)
+    }
+
+    /// Makes the number of the given token, which was read with the given legacy rule, like
+    /// `legacy_hex` for 0xFF.
+    pub(crate) fn legacy_number(
+        rule: Rule,
+        tok: TokInfo,
+        opts: &ReaderOptions,
+    ) -> Result<Atom<TokInfo>, ParsingError> {
+        let err = |msg: &str| {
+            ParsingError::InvalidSyntax(
+                tok.clone(),
+                tok.as_str().to_string(),
+                Some(msg.to_string()),
+            )
+        };
+        if opts.strict {
+            return Err(err("This is not a valid Racket number"));
+        }
+        let string = tok.as_str().replace('_', "");
+        let real = match rule {
+            Rule::legacy_hex => number::read_uinteger(&string[2..], 16).map(Real::integer),
+            Rule::legacy_float => string
+                .trim_end_matches('f')
+                .parse::<f64>()
+                .map(Real::Float)
+                .map_err(|e| e.to_string()),
+            _ => string
+                .parse::<BigInt>()
+                .map(Real::integer)
+                .map_err(|e| e.to_string()),
+        };
+        match real {
+            Ok(real) => Ok(Self::number_atom(Number::Real(real), tok)),
+            Err(msg) => Err(err(&msg)),
+        }
+    }
+
+    /// Parses the elements of a list or vector, skipping sexpr-comments. Also returns the dots
+    /// found in between the elements, with the number of elements that came before each of them.
+    fn parse_elements(pairs: Pairs<Rule>, cx: &Reader) -> Result<Elements, ParsingError> {
+        let mut list = Vec::new();
+        let mut dots = Vec::new();
+        for pair in pairs {
+            if pair.as_rule() == Rule::dot {
+                dots.push((list.len(), cx.tok(pair.as_span())));
+            // this guards for sexpr-comments nested outside of top-level, which shouldn't
+            // throw NothingToParse. Only a sexpr comment can start with `#;`
+            } else if !pair.as_str().starts_with("#;") {
+                list.push(Self::parse_sexpr(pair, cx)?);
+            }
+        }
+        Ok((list, dots))
+    }
+
+    /// Builds a list out of the given elements, handling pair notation like (a b . c) and infix
+    /// notation like (a . < . b), which gets read as (< a b).
+    pub(crate) fn build_list(
+        mut list: Vec<Sexpr<TokInfo>>,
+        dots: Vec<(usize, TokInfo)>,
+        tok: TokInfo,
+    ) -> Result<Sexpr<TokInfo>, ParsingError> {
+        let len = list.len();
+        let misplaced = match &dots[..] {
+            [] => return Ok(Sexpr::List(list, tok)),
+            [(i, _)] if *i > 0 && i + 1 == len => {
+                let tail = list.pop().unwrap();
+                return Ok(Sexpr::DottedList(list, Box::new(tail), tok));
+            }
+            [(i, _), (j, _)] if *i > 0 && *j == i + 1 && *j < len => {
+                let op = list.remove(*i);
+                list.insert(0, op);
+                return Ok(Sexpr::List(list, tok));
+            }
+            // a leading dot is always misplaced, otherwise blame the dot that breaks the pattern
+            [(0, dot), ..] | [(_, dot)] | [_, (_, dot)] => dot,
+            [_, _, (_, dot), ..] => dot,
+        };
+        Err(Self::illegal_dot(misplaced.clone()))
+    }
+
+    pub(crate) fn illegal_dot(dot: TokInfo) -> ParsingError {
+        let found = dot.as_str().to_string();
+        ParsingError::InvalidSyntax(dot, found, Some("Illegal use of `.`".to_string()))
+    }
+
+    /// Checks that the brackets of a list, which starts with its opening bracket, are allowed by
+    /// the reader options.
+    fn check_brackets(list: &Pair<Rule>, cx: &Reader) -> Result<(), ParsingError> {
+        Self::check_bracket(cx.tok(list.as_span().get(..1).unwrap()), cx.opts)
+    }
+
+    /// Checks that the given opening bracket is allowed by the reader options.
+    pub(crate) fn check_bracket(opener: TokInfo, opts: &ReaderOptions) -> Result<(), ParsingError> {
+        let (allowed, name) = match opener.as_str() {
+            "[" => (opts.square_brackets, "Square brackets"),
+            "{" => (opts.curly_brackets, "Curly brackets"),
+            _ => (true, ""),
+        };
+        if allowed {
+            return Ok(());
+        }
+        Err(ParsingError::InvalidSyntax(
+            opener.clone(),
+            opener.as_str().to_string(),
+            Some(format!("{} can't be used in this language", name)),
+        ))
+    }
+
+    /// Parses a vector, like `#(1 2)` or `#3(1 2)`.
+    fn parse_vector(sexp: Pair<Rule>, cx: &Reader) -> Result<Sexpr<TokInfo>, ParsingError> {
+        let span = sexp.as_span();
+        let mut inner = sexp.into_inner();
+        let mut items = inner.next().unwrap();
+        let len = if items.as_rule() == Rule::vector_len {
+            let len = items.as_str().parse::<usize>().ok();
+            items = inner.next().unwrap();
+            Some(len)
+        } else {
+            None
+        };
+        Self::check_brackets(&items, cx)?;
+        let (vector, dots) = Self::parse_elements(items.into_inner(), cx)?;
+        Self::build_vector(vector, dots, len, cx.tok(span))
+    }
+
+    /// Builds a vector out of its elements and its length, if it has one, which is `None` when
+    /// it's too big to be read.
+    pub(crate) fn build_vector(
+        vector: Vec<Sexpr<TokInfo>>,
+        dots: Vec<(usize, TokInfo)>,
+        len: Option<Option<usize>>,
+        tok: TokInfo,
+    ) -> Result<Sexpr<TokInfo>, ParsingError> {
+        if let Some((_, dot)) = dots.first() {
+            return Err(Self::illegal_dot(dot.clone()));
+        }
+        match len {
+            Some(Some(len)) if len >= vector.len() => Ok(Sexpr::Vector(vector, Some(len), tok)),
+            Some(Some(_)) => Err(ParsingError::InvalidSyntax(
+                tok.clone(),
+                tok.as_str().to_string(),
+                Some(format!(
+                    "The vector length is too small, {} values provided",
+                    vector.len()
+                )),
+            )),
+            Some(None) => Err(ParsingError::InvalidSyntax(
+                tok.clone(),
+                tok.as_str().to_string(),
+                Some("The vector length is too large".to_string()),
+            )),
+            None => Ok(Sexpr::Vector(vector, None, tok)),
+        }
+    }
+
+    /// Parses a hash table, like `#hash((a . 1))`.
+    fn parse_hash(sexp: Pair<Rule>, cx: &Reader) -> Result<Sexpr<TokInfo>, ParsingError> {
+        let span = sexp.as_span();
+        let mut inner = sexp.into_inner();
+        let kind = Self::hash_kind(inner.next().unwrap().as_str());
+        let items = inner.next().unwrap();
+        Self::check_brackets(&items, cx)?;
+        let (items, dots) = Self::parse_elements(items.into_inner(), cx)?;
+        Self::build_hash(kind, items, dots, cx.tok(span))
+    }
+
+    /// Gets the kind of hash table of a prefix like `#hasheq`.
+    pub(crate) fn hash_kind(prefix: &str) -> HashKind {
+        match prefix {
+            "#hasheq" => HashKind::Eq,
+            "#hasheqv" => HashKind::Eqv,
+            "#hashalw" => HashKind::EqualAlways,
+            _ => HashKind::Equal,
+        }
+    }
+
+    /// Builds a hash table out of its elements, which have to be pairs like `(key . value)`.
+    pub(crate) fn build_hash(
+        kind: HashKind,
+        items: Vec<Sexpr<TokInfo>>,
+        dots: Vec<(usize, TokInfo)>,
+        tok: TokInfo,
+    ) -> Result<Sexpr<TokInfo>, ParsingError> {
+        if let Some((_, dot)) = dots.first() {
+            return Err(Self::illegal_dot(dot.clone()));
+        }
+        let pairs = items
+            .into_iter()
+            .map(|item| match item {
+                Sexpr::DottedList(mut key, value, _) if key.len() == 1 => {
+                    Ok((key.pop().unwrap(), *value))
+                }
+                other => {
+                    let tok = other.get_decorator().clone();
+                    Err(ParsingError::InvalidSyntax(
+                        tok.clone(),
+                        tok.as_str().to_string(),
+                        Some("A hash table entry has to be a pair, like (key . value)".to_string()),
+                    ))
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Sexpr::Hash(kind, pairs, tok))
+    }
+
+    /// Parses a prefab structure, like `#s(point 1 2)`.
+    fn parse_prefab(sexp: Pair<Rule>, cx: &Reader) -> Result<Sexpr<TokInfo>, ParsingError> {
+        let span = sexp.as_span();
+        let items = sexp.into_inner().next().unwrap();
+        Self::check_brackets(&items, cx)?;
+        let (fields, dots) = Self::parse_elements(items.into_inner(), cx)?;
+        Self::build_prefab(fields, dots, cx.tok(span))
+    }
+
+    /// Builds a prefab structure out of its elements, the first of which is its key.
+    pub(crate) fn build_prefab(
+        mut fields: Vec<Sexpr<TokInfo>>,
+        dots: Vec<(usize, TokInfo)>,
+        tok: TokInfo,
+    ) -> Result<Sexpr<TokInfo>, ParsingError> {
+        if let Some((_, dot)) = dots.first() {
+            return Err(Self::illegal_dot(dot.clone()));
+        }
+        let key_is_valid = match fields.first() {
+            Some(Sexpr::Atom(Atom::Symbol(..), _)) => true,
+            Some(Sexpr::List(key, _)) => {
+                matches!(key.first(), Some(Sexpr::Atom(Atom::Symbol(..), _)))
+            }
+            _ => false,
+        };
+        if !key_is_valid {
+            return Err(ParsingError::InvalidSyntax(
+                tok.clone(),
+                tok.as_str().to_string(),
+                Some(
+                    "A prefab structure has to start with its key, like #s(point 1 2)".to_string(),
+                ),
+            ));
+        }
+        let key = fields.remove(0);
+        Ok(Sexpr::Prefab(Box::new(key), fields, tok))
+    }
+
+    fn parse_sexpr(sexp: Pair<Rule>, cx: &Reader) -> Result<Sexpr<TokInfo>, ParsingError> {
         let mut inner = sexp.into_inner();
         let sexp = inner.next().unwrap();
         match sexp.as_rule() {
             Rule::atom => {
                 let span = sexp.as_span();
-                let atom = Self::parse_atom(sexp)?;
-                Ok(Sexpr::Atom(atom, span.into()))
+                let inner = sexp.into_inner().next().unwrap();
+                let atom = match inner.as_rule() {
+                    Rule::quoted
+                    | Rule::quasiquoted
+                    | Rule::unquoted
+                    | Rule::unquote_spliced
+                    | Rule::syntax_quoted
+                    | Rule::quasisyntax_quoted
+                    | Rule::unsyntaxed
+                    | Rule::unsyntax_spliced
+                    | Rule::boxed => Self::parse_quoted(inner, cx)?,
+                    _ => Self::parse_atom(inner, cx)?,
+                };
+                Ok(Sexpr::Atom(atom, cx.tok(span)))
             }
             Rule::slist => {
                 let span = sexp.as_span();
-                let inner = sexp.into_inner();
-                let mut list = Vec::new();
-                for pair in inner {
-                    // this guards for sexpr-comments nested outside of top-level, which shouldn't
-                    // throw NothingToParse
-                    if !Self::pair_is_sexprcomment(pair.clone()) {
-                        list.push(Self::parse_sexpr(pair)?);
-                    }
-                }
-                Ok(Sexpr::List(list, span.into()))
+                Self::check_brackets(&sexp, cx)?;
+                let (list, dots) = Self::parse_elements(sexp.into_inner(), cx)?;
+                Self::build_list(list, dots, cx.tok(span))
             }
+            Rule::vector => Self::parse_vector(sexp, cx),
+            Rule::hash => Self::parse_hash(sexp, cx),
+            Rule::prefab => Self::parse_prefab(sexp, cx),
             _ => Err(ParsingError::InvalidSyntax(
-                sexp.as_span().into(),
+                cx.tok(sexp.as_span()),
                 sexp.to_string(),
                 Some(
                     "The given token was matched as a sexpr, but wasn't a valid sexpr".to_string(),
@@ -180,19 +1077,222 @@ impl SexprParser {
     }
 }
 
-impl From<pest::error::Error<Rule>> for ParsingError {
-    fn from(e: pest::error::Error<Rule>) -> Self {
-        ParsingError::Pest(e.to_string())
+/// Turns an error of the grammar, found when parsing `input[start..]`, into an `UnexpectedToken`
+/// error. When the brackets of `input[start..end]` are unbalanced, the error is an
+/// `UnbalancedBracket` one instead, as that says more than where the grammar gave up, and when
+/// the grammar ran out of stack it's a `TooDeep` one, though `check_depth` is there to find
+/// those first.
+pub(crate) fn grammar_error(
+    e: pest::error::Error<Rule>,
+    source: &Arc<Source>,
+    start: usize,
+    end: usize,
+) -> ParsingError {
+    use pest::error::{ErrorVariant, InputLocation};
+    let input = source.text();
+    // the grammar gives up when it's about to run out of stack, which only deep nesting can do
+    if let (ErrorVariant::CustomError { message }, InputLocation::Pos(pos)) =
+        (&e.variant, &e.location)
+    {
+        if message == "stack limit reached" {
+            let pos = start + pos;
+            return ParsingError::TooDeep(TokInfo::new(
+                source.clone(),
+                pos,
+                pos + token_len(&input[pos..]),
+            ));
+        }
+    }
+    let (pos, positives) = match (e.location, e.variant) {
+        (InputLocation::Pos(pos), ErrorVariant::ParsingError { positives, .. }) => (pos, positives),
+        (InputLocation::Pos(pos) | InputLocation::Span((pos, _)), _) => (pos, Vec::new()),
+    };
+    unexpected_token(source, start, end, start + pos, positives)
+}
+
+/// Makes the error of the grammar giving up at `pos` in `source.text()[start..end]`, having
+/// tried the given rules there, which is an `UnexpectedToken` one, unless the brackets of the
+/// input are unbalanced, as that says more than where the grammar gave up.
+pub(crate) fn unexpected_token(
+    source: &Arc<Source>,
+    start: usize,
+    end: usize,
+    pos: usize,
+    rules: Vec<Rule>,
+) -> ParsingError {
+    if let Some(bracket) = brackets::find_bracket_error(source, start, end) {
+        return ParsingError::UnbalancedBracket(Box::new(bracket));
+    }
+    let mut expected: Vec<_> = rules.into_iter().filter_map(expected_of).collect();
+    expected.sort();
+    expected.dedup();
+    let tok = TokInfo::new(source.clone(), pos, pos + token_len(&source.text()[pos..]));
+    let found = tok.as_str().to_string();
+    ParsingError::UnexpectedToken(tok, expected, found)
+}
+
+/// Gets the length of the token at the start of the given text, which goes on until a delimiter.
+fn token_len(text: &str) -> usize {
+    let is_delimiter = |c: char| c.is_whitespace() || "()[]{}\";".contains(c);
+    match text.chars().next() {
+        Some(c) if is_delimiter(c) => c.len_utf8(),
+        _ => text.find(is_delimiter).unwrap_or(text.len()),
+    }
+}
+
+/// Gets the kind of token a rule of the grammar stands for, if it's meaningful to a user.
+fn expected_of(rule: Rule) -> Option<Expected> {
+    Some(match rule {
+        Rule::program
+        | Rule::sexpr
+        | Rule::atom
+        | Rule::slist
+        | Rule::vector
+        | Rule::hash
+        | Rule::prefab
+        | Rule::quoted
+        | Rule::quasiquoted
+        | Rule::unquote_spliced
+        | Rule::unquoted
+        | Rule::syntax_quoted
+        | Rule::quasisyntax_quoted
+        | Rule::unsyntax_spliced
+        | Rule::unsyntaxed
+        | Rule::boxed
+        | Rule::sexpr_comment => Expected::Expression,
+        Rule::dot => Expected::Dot,
+        Rule::symbol | Rule::symbol_plain | Rule::symbol_pipe | Rule::symbol_escape => {
+            Expected::Symbol
+        }
+        Rule::keyword => Expected::Keyword,
+        Rule::number
+        | Rule::legacy_number
+        | Rule::legacy_digits
+        | Rule::legacy_hex
+        | Rule::legacy_float
+        | Rule::legacy_integer
+        | Rule::sign
+        | Rule::exactness
+        | Rule::radix2
+        | Rule::radix8
+        | Rule::radix10
+        | Rule::radix16
+        | Rule::inf_nan
+        | Rule::exp_mark10
+        | Rule::exp_mark16
+        | Rule::digit2
+        | Rule::uinteger2
+        | Rule::exp2
+        | Rule::decimal2
+        | Rule::ureal2
+        | Rule::real2
+        | Rule::imag2
+        | Rule::complex2
+        | Rule::digit8
+        | Rule::uinteger8
+        | Rule::exp8
+        | Rule::decimal8
+        | Rule::ureal8
+        | Rule::real8
+        | Rule::imag8
+        | Rule::complex8
+        | Rule::digit10
+        | Rule::uinteger10
+        | Rule::exp10
+        | Rule::decimal10
+        | Rule::ureal10
+        | Rule::real10
+        | Rule::imag10
+        | Rule::complex10
+        | Rule::digit16
+        | Rule::uinteger16
+        | Rule::exp16
+        | Rule::decimal16
+        | Rule::ureal16
+        | Rule::real16
+        | Rule::imag16
+        | Rule::complex16 => Expected::Number,
+        Rule::bool | Rule::bool_false | Rule::bool_true => Expected::Boolean,
+        Rule::character
+        | Rule::char_named
+        | Rule::char_octal
+        | Rule::char_unicode
+        | Rule::char_any => Expected::Character,
+        Rule::string | Rule::byte_string | Rule::regexp | Rule::regexp_kind | Rule::here_string => {
+            Expected::String
+        }
+        Rule::raw_string | Rule::escape | Rule::byte_escape => Expected::StringContent,
+        Rule::predefined
+        | Rule::octal
+        | Rule::byte
+        | Rule::legacy_unicode
+        | Rule::unicode
+        | Rule::continuation => Expected::Escape,
+        Rule::here_terminator | Rule::here_body => Expected::HereStringTerminator,
+        Rule::vector_len | Rule::vector_items | Rule::hash_kind => Expected::OpeningBracket,
+        Rule::EOI => Expected::EndOfInput,
+        _ => return None,
+    })
+}
+
+/// Gets the length of the byte order mark at the start of `source.text()[start..end]`, which is
+/// skipped like whitespace when it's at the very start of the input, as editors on Windows often
+/// save files with one.
+pub(crate) fn bom_len(source: &Source, start: usize, end: usize) -> usize {
+    let at_start = start == 0 && source.offset() == 0;
+    if at_start && source.text()[..end].starts_with('\u{feff}') {
+        return '\u{feff}'.len_utf8();
+    }
+    0
+}
+
+/// Checks if a prefix like `'` in the pairs was read as a part of a symbol, like `'` in `(a ')`,
+/// as there was no form after it to go with. Text added after the input can change that, when
+/// the prefix is at the end of it or before comments up to the end.
+pub(crate) fn has_lone_prefix(pairs: Pairs<Rule>) -> bool {
+    pairs.flatten().any(|pair| {
+        let span = pair.as_span();
+        let symbol = span.as_str();
+        // `,@` without a form after it is read as a `,` before a symbol like `@`
+        let after_comma = span.get_input()[..span.start()].ends_with(',');
+        pair.as_rule() == Rule::symbol
+            && (symbol.starts_with(['\'', '`', ','])
+                || ["#'", "#`", "#,", "#&"]
+                    .iter()
+                    .any(|p| symbol.starts_with(p))
+                || after_comma && symbol.starts_with('@'))
+    })
+}
+
+/// Finds the start of the first line after `from` that looks like the start of a top-level form,
+/// or the end of the input if there is none.
+fn next_form_start(input: &str, from: usize) -> usize {
+    let mut offset = from;
+    while let Some(newline) = input[offset..].find(['\n', '\r']) {
+        offset += newline + 1;
+        match input[offset..].chars().next() {
+            Some(c) if !c.is_whitespace() && !matches!(c, ')' | ']' | '}' | ';') => return offset,
+            Some(_) => {}
+            None => break,
+        }
     }
+    input.len()
 }
 
 #[cfg(test)]
 mod sexpr_parser_tests {
-    use super::SexprParser;
+    use std::sync::Arc;
+
+    use super::{ReaderOptions, SexprParser};
     use crate::{
-        errors::ParsingError,
-        sexpr::{Atom, Sexpr},
+        cst::Cst,
+        errors::{Expected, ParsingError},
+        fast_parser::FastParser,
+        number::Real,
+        sexpr::{Atom, HashKind, RegexpKind, Sexpr, Source},
+        test_util::random_inputs,
     };
+    use num_bigint::BigInt;
 
     #[test]
     fn test_parse_slist_simple() {
@@ -277,6 +1377,128 @@ mod sexpr_parser_tests {
         );
     }
 
+    #[test]
+    fn test_blockcomment_nested() {
+        let parsed = SexprParser::parse_all("1 #| outer #| inner |# still comment |# 2 #|#||#|# 3")
+            .unwrap()
+            .into_iter()
+            .map(|s| s.untag())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parsed,
+            vec![
+                Sexpr::Atom(Atom::Integer(1, ()), ()),
+                Sexpr::Atom(Atom::Integer(2, ()), ()),
+                Sexpr::Atom(Atom::Integer(3, ()), ()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blockcomment_unclosed_nested() {
+        let parsed = SexprParser::parse_str("(1 #| outer #| inner |# 2)");
+        assert!(parsed.is_err());
+        // the `#|` opens a nested comment even when it's followed by a `#`
+        for input in ["#|#|#", "#|x#|#", "#|#|# |#"] {
+            assert!(SexprParser::parse_all(input).is_err(), "{:?}", input);
+            assert!(FastParser::parse_all(input).is_err(), "{:?}", input);
+        }
+        assert!(SexprParser::parse_all("#|#|# |# |#").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_shebang_comment() {
+        let parsed = SexprParser::parse_all(
+            "#!/usr/bin/env racket\n(1 #! a comment \\\n still a comment\n 2)",
+        )
+        .unwrap()
+        .into_iter()
+        .map(|s| s.untag())
+        .collect::<Vec<_>>();
+        assert_eq!(
+            parsed,
+            vec![Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::Integer(1, ()), ()),
+                    Sexpr::Atom(Atom::Integer(2, ()), ()),
+                ],
+                ()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_eof_comment() {
+        let parsed = SexprParser::parse_all("(1 2) #!eof (3 \" |# )")
+            .unwrap()
+            .into_iter()
+            .map(|s| s.untag())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parsed,
+            vec![Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::Integer(1, ()), ()),
+                    Sexpr::Atom(Atom::Integer(2, ()), ()),
+                ],
+                ()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_line_comment_at_end() {
+        for input in ["(a) ; done", "(a)\n; done", "(a)\r\n;", "(a) #; b ;"] {
+            let parsed = SexprParser::parse_all(input).unwrap();
+            assert_eq!(parsed.len(), 1, "{:?}", input);
+            assert_eq!(parsed, FastParser::parse_all(input).unwrap());
+        }
+        assert!(SexprParser::parse_str("(a ; done").is_err());
+    }
+
+    #[test]
+    fn test_platform_line_endings() {
+        let unix = "(define (f x)\n  ; the body\n  x)\n\n\"a\nb\" #| c\n |# (f 1)\n; done";
+        let expected = SexprParser::parse_all(unix).unwrap();
+        let untagged =
+            |forms: Vec<Sexpr<_>>| forms.into_iter().map(|f| f.untag()).collect::<Vec<_>>();
+        // Windows, classic Mac OS, and a BOM that some editors save at the start of the file
+        for (newline, bom) in [
+            ("\n", "\u{feff}"),
+            ("\r\n", ""),
+            ("\r\n", "\u{feff}"),
+            ("\r", ""),
+        ] {
+            let input = format!("{bom}{}", unix.replace('\n', newline));
+            for parsed in [
+                SexprParser::parse_all(&input).unwrap(),
+                FastParser::parse_all(&input).unwrap(),
+                SexprParser::parse_all_recovering(&input).0,
+            ] {
+                let starts: Vec<_> = parsed
+                    .iter()
+                    .map(|f| f.get_decorator().start_line_col())
+                    .collect();
+                assert_eq!(
+                    starts,
+                    [(1, 1 + bom.len().min(1)), (5, 1), (7, 5)],
+                    "{:?}",
+                    input
+                );
+                let tok = parsed[0].get_decorator();
+                assert_eq!(tok.as_str(), &input[tok.start()..tok.end()]);
+                assert!(tok.as_str().starts_with('(') && tok.as_str().ends_with(')'));
+                // only the string keeps the line break as it was
+                let mut parsed = untagged(parsed);
+                if let Sexpr::Atom(Atom::String(s, ()), ()) = &mut parsed[1] {
+                    assert_eq!(*s, format!("a{newline}b"));
+                    *s = "a\nb".to_string();
+                }
+                assert_eq!(parsed, untagged(expected.clone()), "{:?}", input);
+            }
+        }
+    }
+
     #[test]
     fn test_sexprcomment_atom() {
         let parsed = SexprParser::parse_str("(#;1 1 2 #;3 3)").unwrap().untag();
@@ -350,18 +1572,218 @@ mod sexpr_parser_tests {
 
     #[test]
     fn test_parse_string_escapes() {
-        let parsed = SexprParser::parse_str(r#""a\nb\x0Fc\u{a}d\u{AbAbAb}e""#)
+        let parsed = SexprParser::parse_str(r#""a\nb\x0Fc\u{a}d\u{1F600}e""#)
             .unwrap()
             .untag();
+        assert_eq!(
+            parsed,
+            Sexpr::Atom(Atom::String("a\nb\x0Fc\nd😀e".to_string(), ()), ())
+        );
+    }
+
+    #[test]
+    fn test_parse_string_racket_escapes() {
+        let parsed = SexprParser::parse_str(
+            r#""\a\b\t\n\v\f\r\e\"\'\\ \101\0\x41\x7\u3bb\U1F600\uD83D\uDE00""#,
+        )
+        .unwrap()
+        .untag();
         assert_eq!(
             parsed,
             Sexpr::Atom(
-                Atom::String(r#"a\nb\x0Fc\u{a}d\u{AbAbAb}e"#.to_string(), ()),
+                Atom::String(
+                    "\u{7}\u{8}\t\n\u{b}\u{c}\r\u{1b}\"'\\ A\0A\u{7}λ😀😀".to_string(),
+                    ()
+                ),
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_string_continuation() {
+        let parsed = SexprParser::parse_str("\"a \\\n b\\\r\nc\"")
+            .unwrap()
+            .untag();
+        assert_eq!(
+            parsed,
+            Sexpr::Atom(Atom::String("a  bc".to_string(), ()), ())
+        );
+    }
+
+    #[test]
+    fn test_parse_string_bad_escapes() {
+        for input in [r#""\uD83D""#, r#""\uD83Dx""#, r#""\U110000""#, r#""\q""#] {
+            assert!(SexprParser::parse_str(input).is_err(), "{}", input);
+        }
+        let strict = ReaderOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let parsed = SexprParser::parse_str_with(r#""\u{a}""#, &strict);
+        assert!(matches!(parsed, Err(ParsingError::InvalidSyntax(..))));
+    }
+
+    #[test]
+    fn test_string_keeps_lexeme() {
+        let parsed = SexprParser::parse_str(r#""a\x41""#).unwrap();
+        match parsed {
+            Sexpr::Atom(Atom::String(s, tok), _) => {
+                assert_eq!(s, "aA");
+                assert_eq!(tok.as_str(), r#""a\x41""#);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_display_string() {
+        let parsed = SexprParser::parse_str(r#"("a\"b" "\\" "\x41\n\e\x1")"#).unwrap();
+        assert_eq!(parsed.to_string(), r#"("a\"b" "\\" "A\n\e\u0001")"#);
+    }
+
+    #[test]
+    fn test_parse_byte_strings() {
+        let parsed = SexprParser::parse_str(r#"(#"abc" #"a\0\xFF\n" #"")"#)
+            .unwrap()
+            .untag();
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::ByteString(b"abc".to_vec(), ()), ()),
+                    Sexpr::Atom(Atom::ByteString(b"a\0\xFF\n".to_vec(), ()), ()),
+                    Sexpr::Atom(Atom::ByteString(vec![], ()), ()),
+                ],
+                ()
+            )
+        );
+        for input in [r#"#"λ""#, r#"#"\u41""#, r#"#"\777""#] {
+            assert!(SexprParser::parse_str(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_regexps() {
+        let parsed = SexprParser::parse_str(r##"(#rx"a+" #px"\\d+" #rx#"a" #px#"\\s")"##)
+            .unwrap()
+            .untag();
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::Regexp("a+".to_string(), RegexpKind::Rx, ()), ()),
+                    Sexpr::Atom(Atom::Regexp(r"\d+".to_string(), RegexpKind::Px, ()), ()),
+                    Sexpr::Atom(Atom::ByteRegexp(b"a".to_vec(), RegexpKind::Rx, ()), ()),
+                    Sexpr::Atom(Atom::ByteRegexp(b"\\s".to_vec(), RegexpKind::Px, ()), ()),
+                ],
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_here_strings() {
+        let parsed = SexprParser::parse_all("(#<<EOF\nhello\n  EOF\nworld \"\nEOF\n x)").unwrap();
+        match &parsed[0] {
+            Sexpr::List(items, _) => {
+                match &items[0] {
+                    Sexpr::Atom(Atom::String(s, tok), _) => {
+                        assert_eq!(s, "hello\n  EOF\nworld \"");
+                        assert_eq!(tok.start_line_col(), (1, 2));
+                        assert_eq!(tok.end_line_col(), (5, 4));
+                    }
+                    other => panic!("{:?}", other),
+                }
+                assert_eq!(
+                    items[1].clone().untag(),
+                    Sexpr::Atom(Atom::Symbol("x".to_string(), ()), ())
+                );
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let parsed = SexprParser::parse_str("#<<END\r\nEND").unwrap().untag();
+        assert_eq!(parsed, Sexpr::Atom(Atom::String(String::new(), ()), ()));
+        let parsed = SexprParser::parse_str("#<<END\r\na\r\nb\r\nEND")
+            .unwrap()
+            .untag();
+        assert_eq!(
+            parsed,
+            Sexpr::Atom(Atom::String("a\r\nb".to_string(), ()), ())
+        );
+        assert!(SexprParser::parse_str("#<<END\na\nEN").is_err());
+    }
+
+    #[test]
+    fn test_display_byte_strings_and_regexps() {
+        let input = r##"(#"a\"b\\\0\377" #rx"a+" #px"\\d+" #rx#"a\n" #<<EOF
+a "quote"
+EOF
+)"##;
+        let parsed = SexprParser::parse_str(input).unwrap();
+        assert_eq!(
+            parsed.to_string(),
+            r##"(#"a\"b\\\000\377" #rx"a+" #px"\\d+" #rx#"a\n" "a \"quote\"")"##
+        );
+        // an octal escape is always three digits, so a digit after it isn't read as part of it
+        let bytes = Sexpr::Atom(Atom::ByteString(b"\x001\xFF7\x078".to_vec(), ()), ());
+        let regexp = Sexpr::Atom(Atom::ByteRegexp(b"\x012".to_vec(), RegexpKind::Rx, ()), ());
+        assert_eq!(bytes.to_string(), r#"#"\0001\3777\0078""#);
+        for form in [bytes, regexp] {
+            let parsed = SexprParser::parse_str(&form.to_string()).unwrap().untag();
+            assert_eq!(parsed, form);
+        }
+    }
+
+    #[test]
+    fn test_parse_chars() {
+        let parsed = SexprParser::parse_str(
+            r"(#\a #\λ #\( #\) #\space #\newline #\nul #\101 #\u3BB #\U1F600)",
+        )
+        .unwrap()
+        .untag();
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::Char('a', ()), ()),
+                    Sexpr::Atom(Atom::Char('λ', ()), ()),
+                    Sexpr::Atom(Atom::Char('(', ()), ()),
+                    Sexpr::Atom(Atom::Char(')', ()), ()),
+                    Sexpr::Atom(Atom::Char(' ', ()), ()),
+                    Sexpr::Atom(Atom::Char('\n', ()), ()),
+                    Sexpr::Atom(Atom::Char('\0', ()), ()),
+                    Sexpr::Atom(Atom::Char('A', ()), ()),
+                    Sexpr::Atom(Atom::Char('λ', ()), ()),
+                    Sexpr::Atom(Atom::Char('😀', ()), ()),
+                ],
                 ()
             )
         );
     }
 
+    #[test]
+    fn test_parse_char_literal_space() {
+        let parsed = SexprParser::parse_str(r"(#\  #\u)").unwrap().untag();
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::Char(' ', ()), ()),
+                    Sexpr::Atom(Atom::Char('u', ()), ()),
+                ],
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_display_chars() {
+        let parsed = SexprParser::parse_str(r"(#\a #\linefeed #\tab #\( #\u1 #\λ)").unwrap();
+        assert_eq!(parsed.to_string(), r"(#\a #\newline #\tab #\( #\u0001 #\λ)");
+    }
+
     #[test]
     fn test_parse_string_nested() {
         let parsed = SexprParser::parse_str(r#"("a" "b" "c")"#).unwrap().untag();
@@ -457,6 +1879,250 @@ mod sexpr_parser_tests {
         assert_eq!(parsed, Sexpr::Atom(Atom::Float(20.0, ()), ()));
     }
 
+    fn parse_number(input: &str) -> Atom<()> {
+        match SexprParser::parse_str(input).unwrap().untag() {
+            Sexpr::Atom(atom, _) => atom,
+            other => panic!("{} parsed as {:?}", input, other),
+        }
+    }
+
+    #[test]
+    fn test_parse_rationals() {
+        assert_eq!(parse_number("1/3"), Atom::Rational(1.into(), 3.into(), ()));
+        assert_eq!(
+            parse_number("-2/4"),
+            Atom::Rational((-1).into(), 2.into(), ())
+        );
+        assert_eq!(parse_number("6/3"), Atom::Integer(2, ()));
+        assert_eq!(
+            parse_number("#e1.5"),
+            Atom::Rational(3.into(), 2.into(), ())
+        );
+        assert_eq!(parse_number("#e1.2e2"), Atom::Integer(120, ()));
+        assert_eq!(parse_number("#i1/4"), Atom::Float(0.25, ()));
+        assert!(matches!(
+            SexprParser::parse_str("1/0"),
+            Err(ParsingError::InvalidSyntax(..))
+        ));
+        assert!(SexprParser::parse_str("#e1/0").is_err());
+        // but read inexactly, it's an infinity
+        assert_eq!(parse_number("#i1/0"), Atom::Float(f64::INFINITY, ()));
+        assert_eq!(parse_number("#i-1/0"), Atom::Float(f64::NEG_INFINITY, ()));
+        assert_eq!(parse_number("1#/0"), Atom::Float(f64::INFINITY, ()));
+        assert!(matches!(parse_number("#i0/0"), Atom::Float(f, ()) if f.is_nan()));
+    }
+
+    #[test]
+    fn test_parse_big_integers() {
+        let parsed = SexprParser::parse_str("(factorial 100000000000000000000)")
+            .unwrap()
+            .untag();
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::Symbol("factorial".to_string(), ()), ()),
+                    Sexpr::Atom(
+                        Atom::BigInteger("100000000000000000000".parse().unwrap(), ()),
+                        ()
+                    ),
+                ],
+                ()
+            )
+        );
+        assert_eq!(
+            parse_number("-9223372036854775808"),
+            Atom::Integer(i64::MIN, ())
+        );
+        assert_eq!(
+            parse_number("9223372036854775808"),
+            Atom::BigInteger(BigInt::from(i64::MAX) + 1, ())
+        );
+        assert_eq!(
+            parse_number("#e1e20"),
+            Atom::BigInteger(BigInt::from(10).pow(20), ())
+        );
+        assert_eq!(
+            parse_number("1/100000000000000000000"),
+            Atom::Rational(1.into(), BigInt::from(10).pow(20), ())
+        );
+        assert_eq!(
+            parse_number("0xFFFFFFFFFFFFFFFFFF"),
+            Atom::BigInteger(BigInt::from(2).pow(72) - 1, ())
+        );
+        assert_eq!(
+            parse_number("-100_000_000_000_000_000_000"),
+            Atom::BigInteger(-BigInt::from(10).pow(20), ())
+        );
+    }
+
+    #[test]
+    fn test_huge_exact_exponent() {
+        let parsed = SexprParser::parse_str("#e1e999999999");
+        assert!(matches!(parsed, Err(ParsingError::InvalidSyntax(..))));
+        assert_eq!(parse_number("1e999999999"), Atom::Float(f64::INFINITY, ()));
+        // exponents that don't even fit in an i32 are still read inexactly
+        assert_eq!(
+            parse_number("1e99999999999"),
+            Atom::Float(f64::INFINITY, ())
+        );
+        assert_eq!(
+            parse_number("-1e99999999999"),
+            Atom::Float(f64::NEG_INFINITY, ())
+        );
+        assert_eq!(parse_number("1e-99999999999"), Atom::Float(0.0, ()));
+        assert_eq!(parse_number("0e99999999999"), Atom::Float(0.0, ()));
+        assert_eq!(
+            parse_number("#b1e11111111111111111111111111111111111"),
+            Atom::Float(f64::INFINITY, ())
+        );
+        assert_eq!(
+            parse_number("#b0e11111111111111111111111111111111111"),
+            Atom::Float(0.0, ())
+        );
+        assert!(SexprParser::parse_str("#e1e99999999999").is_err());
+        assert!(SexprParser::parse_str("#e1e-99999999999").is_err());
+    }
+
+    #[test]
+    fn test_parse_exponents() {
+        assert_eq!(parse_number("6.02e23"), Atom::Float(6.02e23, ()));
+        assert_eq!(parse_number("1E-2"), Atom::Float(0.01, ()));
+        assert_eq!(parse_number("-.5e1"), Atom::Float(-5.0, ()));
+        assert_eq!(parse_number("1#.#"), Atom::Float(10.0, ()));
+    }
+
+    #[test]
+    fn test_parse_radix() {
+        assert_eq!(parse_number("#x1F"), Atom::Integer(31, ()));
+        assert_eq!(parse_number("#X-ff"), Atom::Integer(-255, ()));
+        assert_eq!(parse_number("#b1010"), Atom::Integer(10, ()));
+        assert_eq!(parse_number("#o17"), Atom::Integer(15, ()));
+        assert_eq!(parse_number("#d17"), Atom::Integer(17, ()));
+        assert_eq!(
+            parse_number("#e#x1/2"),
+            Atom::Rational(1.into(), 2.into(), ())
+        );
+        assert_eq!(parse_number("#x#i10"), Atom::Float(16.0, ()));
+        assert_eq!(parse_number("#b1.1"), Atom::Float(1.5, ()));
+    }
+
+    #[test]
+    fn test_parse_inf_nan() {
+        assert_eq!(parse_number("+inf.0"), Atom::Float(f64::INFINITY, ()));
+        assert_eq!(parse_number("-inf.0"), Atom::Float(f64::NEG_INFINITY, ()));
+        assert!(matches!(parse_number("-nan.0"), Atom::Float(f, ()) if f.is_nan()));
+    }
+
+    #[test]
+    fn test_parse_complex() {
+        assert_eq!(
+            parse_number("1+2i"),
+            Atom::Complex(Real::Integer(1), Real::Integer(2), ())
+        );
+        assert_eq!(
+            parse_number("-i"),
+            Atom::Complex(Real::Integer(0), Real::Integer(-1), ())
+        );
+        assert_eq!(
+            parse_number("1/2-3.5i"),
+            Atom::Complex(Real::Float(0.5), Real::Float(-3.5), ())
+        );
+        assert_eq!(
+            parse_number("+inf.0i"),
+            Atom::Complex(Real::Integer(0), Real::Float(f64::INFINITY), ())
+        );
+        assert_eq!(parse_number("5+0i"), Atom::Integer(5, ()));
+        assert_eq!(parse_number("3@0"), Atom::Integer(3, ()));
+    }
+
+    #[test]
+    fn test_number_like_symbols() {
+        for sym in ["1+", "2nd", "+", "-", "...", "1/2/3", "+inf", "-i-"] {
+            assert_eq!(parse_number(sym), Atom::Symbol(sym.to_string(), ()));
+        }
+    }
+
+    #[test]
+    fn test_display_numbers() {
+        let parsed =
+            SexprParser::parse_str("(1/3 2.0 6.02e23 -inf.0 1-2i 1.5+inf.0i 20f)").unwrap();
+        assert_eq!(
+            parsed.to_string(),
+            "(1/3 2.0 6.02e23 -inf.0 1-2i 1.5+inf.0i 20.0)"
+        );
+    }
+
+    #[test]
+    fn test_strict_numbers() {
+        let strict = ReaderOptions {
+            strict: true,
+            ..Default::default()
+        };
+        for input in ["0x1F", "20f", "1_000", "1_000.5"] {
+            assert!(SexprParser::parse_str(input).is_ok());
+            assert!(matches!(
+                SexprParser::parse_str_with(input, &strict),
+                Err(ParsingError::InvalidSyntax(..))
+            ));
+        }
+        assert_eq!(parse_number("1_000"), Atom::Integer(1000, ()));
+        assert!(SexprParser::parse_str_with("(1/3 #x1F 1.5)", &strict).is_ok());
+    }
+
+    #[test]
+    fn test_decimal_as_exact() {
+        let exact = ReaderOptions {
+            decimal_as_exact: true,
+            ..Default::default()
+        };
+        for (input, expected) in [
+            ("0.1", "1/10"),
+            ("1.5e2", "150"),
+            ("#i0.5", "0.5"),
+            ("+inf.0", "+inf.0"),
+            ("1+0.5i", "1+1/2i"),
+            ("7", "7"),
+        ] {
+            let parsed = SexprParser::parse_str_with(input, &exact).unwrap();
+            assert_eq!(parsed.untag().to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_disallowed_brackets_and_booleans() {
+        let opts = ReaderOptions {
+            square_brackets: false,
+            long_booleans: false,
+            ..Default::default()
+        };
+        for (input, at, found) in [
+            ("(cond [else 1])", (1, 7), "["),
+            ("#[1 2]", (1, 2), "["),
+            ("#hash[(1 . 2)]", (1, 6), "["),
+            ("(#true)", (1, 2), "#true"),
+        ] {
+            assert!(SexprParser::parse_str(input).is_ok());
+            match SexprParser::parse_str_with(input, &opts) {
+                Err(ParsingError::InvalidSyntax(tok, token, Some(_))) => {
+                    assert_eq!((tok.start_line_col(), token.as_str()), (at, found));
+                }
+                other => panic!("{}: {:?}", input, other),
+            }
+        }
+        assert!(SexprParser::parse_str_with("({#t} #f)", &opts).is_ok());
+    }
+
+    #[test]
+    fn test_case_folding() {
+        let opts = ReaderOptions {
+            case_sensitive: false,
+            ..Default::default()
+        };
+        let parsed = SexprParser::parse_str_with(r"(MyFunc #:Key |Keep| A\B λΛ)", &opts).unwrap();
+        assert_eq!(parsed.untag().to_string(), r"(myfunc #:key Keep aB λλ)");
+    }
+
     #[test]
     fn test_sval_symbol() {
         let parsed = SexprParser::parse_str("'b").unwrap().untag();
@@ -637,6 +2303,63 @@ mod sexpr_parser_tests {
         );
     }
 
+    #[test]
+    fn test_sval_unquote_spliced() {
+        let parsed = SexprParser::parse_str("`(1 ,@xs)").unwrap().untag();
+        assert_eq!(
+            parsed,
+            Sexpr::Atom(
+                Atom::QuasiQuoted(
+                    Box::new(Sexpr::List(
+                        vec![
+                            Sexpr::Atom(Atom::Integer(1, ()), ()),
+                            Sexpr::Atom(
+                                Atom::UnquoteSpliced(
+                                    Box::new(Sexpr::Atom(Atom::Symbol("xs".to_string(), ()), ())),
+                                    ()
+                                ),
+                                ()
+                            ),
+                        ],
+                        ()
+                    )),
+                    ()
+                ),
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_sval_syntax_quotes() {
+        let parsed = SexprParser::parse_str("(#'a #`b #,c #,@d)")
+            .unwrap()
+            .untag();
+        let sym = |s: &str| Box::new(Sexpr::Atom(Atom::Symbol(s.to_string(), ()), ()));
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::SyntaxQuoted(sym("a"), ()), ()),
+                    Sexpr::Atom(Atom::QuasiSyntaxQuoted(sym("b"), ()), ()),
+                    Sexpr::Atom(Atom::Unsyntaxed(sym("c"), ()), ()),
+                    Sexpr::Atom(Atom::UnsyntaxSpliced(sym("d"), ()), ()),
+                ],
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_display_reader_abbreviations() {
+        let input = "(syntax-case stx () [(_ e ...) #`(begin #,@(map f #'(e ...)) ,@xs ,x 'y)])";
+        let parsed = SexprParser::parse_str(input).unwrap();
+        assert_eq!(
+            parsed.to_string(),
+            "(syntax-case stx () ((_ e ...) #`(begin #,@(map f #'(e ...)) ,@xs ,x 'y)))"
+        );
+    }
+
     #[test]
     fn test_sval_list_simple() {
         let parsed = SexprParser::parse_str("'(1 \"bla\" #false bla 'bla)")
@@ -690,6 +2413,81 @@ mod sexpr_parser_tests {
         assert_eq!(parsed, Sexpr::Atom(Atom::Symbol(".".to_string(), ()), ()));
     }
 
+    #[test]
+    fn test_parse_dotted_pair() {
+        let parsed = SexprParser::parse_str("(a b . c)").unwrap().untag();
+        assert_eq!(
+            parsed,
+            Sexpr::DottedList(
+                vec![
+                    Sexpr::Atom(Atom::Symbol("a".to_string(), ()), ()),
+                    Sexpr::Atom(Atom::Symbol("b".to_string(), ()), ()),
+                ],
+                Box::new(Sexpr::Atom(Atom::Symbol("c".to_string(), ()), ())),
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_alist() {
+        let parsed = SexprParser::parse_str("'((a . 1) [b . (2)])").unwrap();
+        assert_eq!(parsed.to_string(), "'((a . 1) (b . (2)))");
+    }
+
+    #[test]
+    fn test_parse_dot_symbols() {
+        let parsed = SexprParser::parse_str("(... .5 .a)").unwrap().untag();
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::Symbol("...".to_string(), ()), ()),
+                    Sexpr::Atom(Atom::Float(0.5, ()), ()),
+                    Sexpr::Atom(Atom::Symbol(".a".to_string(), ()), ()),
+                ],
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_infix_dot() {
+        let parsed = SexprParser::parse_str("(1 . < . 2)").unwrap().untag();
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::Symbol("<".to_string(), ()), ()),
+                    Sexpr::Atom(Atom::Integer(1, ()), ()),
+                    Sexpr::Atom(Atom::Integer(2, ()), ()),
+                ],
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_misplaced_dots() {
+        for (input, col) in [
+            ("(. a)", 2),
+            ("(a . b c)", 4),
+            ("(a .)", 4),
+            ("(a . b . )", 8),
+            ("(a . . b)", 6),
+            ("(a . b . c . d)", 12),
+            ("#(a . b)", 5),
+        ] {
+            match SexprParser::parse_str(input) {
+                Err(ParsingError::InvalidSyntax(pos, tok, _)) => {
+                    assert_eq!(tok, ".");
+                    assert_eq!(pos.start_line_col(), (1, col), "{}", input);
+                }
+                res => panic!("{} parsed as {:?}", input, res),
+            }
+        }
+    }
+
     #[test]
     fn test_sval_list_nested() {
         let parsed = SexprParser::parse_str("'(1 '(bla '(bla bla) 'fufu #true) 'faf)")
@@ -771,7 +2569,7 @@ mod sexpr_parser_tests {
             Sexpr::List(
                 vec![
                     Sexpr::Atom(Atom::Symbol("bla".to_string(), ()), ()),
-                    Sexpr::Atom(Atom::Symbol("#:when".to_string(), ()), ()),
+                    Sexpr::Atom(Atom::Keyword("when".to_string(), ()), ()),
                     Sexpr::Atom(Atom::Boolean(true, ()), ()),
                 ],
                 ()
@@ -779,6 +2577,58 @@ mod sexpr_parser_tests {
         );
     }
 
+    #[test]
+    fn test_parse_pipe_symbols() {
+        let parsed = SexprParser::parse_str(r"(|hello world| a|b c|d |1| \1 a\ b \| |a\|)")
+            .unwrap()
+            .untag();
+        let sym = |s: &str| Sexpr::Atom(Atom::Symbol(s.to_string(), ()), ());
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    sym("hello world"),
+                    sym("ab cd"),
+                    sym("1"),
+                    sym("1"),
+                    sym("a b"),
+                    sym("|"),
+                    sym("a\\"),
+                ],
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_keywords() {
+        let parsed = SexprParser::parse_str("(f #:key 1 #:|a b| #:2)")
+            .unwrap()
+            .untag();
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::Symbol("f".to_string(), ()), ()),
+                    Sexpr::Atom(Atom::Keyword("key".to_string(), ()), ()),
+                    Sexpr::Atom(Atom::Integer(1, ()), ()),
+                    Sexpr::Atom(Atom::Keyword("a b".to_string(), ()), ()),
+                    Sexpr::Atom(Atom::Keyword("2".to_string(), ()), ()),
+                ],
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_display_symbols() {
+        let parsed = SexprParser::parse_str(r"(abc |a b| |1| |#t| |.| \| #:key #:|x y|)").unwrap();
+        assert_eq!(
+            parsed.to_string(),
+            r"(abc |a b| |1| |#t| |.| \| #:key #:|x y|)"
+        );
+    }
+
     #[test]
     fn test_balanced_parens1() {
         let parsed = SexprParser::parse_str("[1 (2 {1 2 3}) 3]");
@@ -797,6 +2647,180 @@ mod sexpr_parser_tests {
         assert!(parsed.is_err());
     }
 
+    #[test]
+    fn test_parse_vector() {
+        let parsed = SexprParser::parse_str("(htdp-settings #(#t constructor #[1] #{} #()))")
+            .unwrap()
+            .untag();
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Atom(Atom::Symbol("htdp-settings".to_string(), ()), ()),
+                    Sexpr::Vector(
+                        vec![
+                            Sexpr::Atom(Atom::Boolean(true, ()), ()),
+                            Sexpr::Atom(Atom::Symbol("constructor".to_string(), ()), ()),
+                            Sexpr::Vector(vec![Sexpr::Atom(Atom::Integer(1, ()), ())], None, ()),
+                            Sexpr::Vector(vec![], None, ()),
+                            Sexpr::Vector(vec![], None, ()),
+                        ],
+                        None,
+                        ()
+                    ),
+                ],
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_vector_len() {
+        let parsed = SexprParser::parse_str("#3(1 #;2 2)").unwrap().untag();
+        assert_eq!(
+            parsed,
+            Sexpr::Vector(
+                vec![
+                    Sexpr::Atom(Atom::Integer(1, ()), ()),
+                    Sexpr::Atom(Atom::Integer(2, ()), ()),
+                ],
+                Some(3),
+                ()
+            )
+        );
+        let parsed = SexprParser::parse_str("#2(1 2 3)");
+        assert!(matches!(parsed, Err(ParsingError::InvalidSyntax(..))));
+        // a length too big to be read isn't taken as too small
+        let input = "#99999999999999999999(1)";
+        for parsed in [
+            SexprParser::parse_str(input),
+            FastParser::parse_all(input).map(|mut forms| forms.remove(0)),
+        ] {
+            match parsed {
+                Err(ParsingError::InvalidSyntax(_, _, Some(msg))) => {
+                    assert_eq!(msg, "The vector length is too large")
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_vector_no_whitespace_after_hash() {
+        let parsed = SexprParser::parse_str("(# (1))").unwrap().untag();
+        assert!(matches!(parsed, Sexpr::List(l, _) if l.len() == 2));
+    }
+
+    #[test]
+    fn test_display_vector() {
+        let parsed = SexprParser::parse_str("(a #(1 #[2]) #3(x) (()))").unwrap();
+        assert_eq!(parsed.to_string(), "(a #(1 #(2)) #3(x) (()))");
+    }
+
+    #[test]
+    fn test_parse_hash() {
+        let parsed = SexprParser::parse_str(
+            r#"(#hash((a . 1) ["b" . (2 3)]) #hasheq() #hasheqv{(1 . 2)} #hashalw((x . y)))"#,
+        )
+        .unwrap()
+        .untag();
+        let sym = |s: &str| Sexpr::Atom(Atom::Symbol(s.to_string(), ()), ());
+        let int = |i| Sexpr::Atom(Atom::Integer(i, ()), ());
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    Sexpr::Hash(
+                        HashKind::Equal,
+                        vec![
+                            (sym("a"), int(1)),
+                            (
+                                Sexpr::Atom(Atom::String("b".to_string(), ()), ()),
+                                Sexpr::List(vec![int(2), int(3)], ())
+                            ),
+                        ],
+                        ()
+                    ),
+                    Sexpr::Hash(HashKind::Eq, vec![], ()),
+                    Sexpr::Hash(HashKind::Eqv, vec![(int(1), int(2))], ()),
+                    Sexpr::Hash(HashKind::EqualAlways, vec![(sym("x"), sym("y"))], ()),
+                ],
+                ()
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_hash_bad_entries() {
+        for (input, tok) in [
+            ("#hash((a 1))", "(a 1)"),
+            ("#hash((a . 1) b)", "b"),
+            ("#hash((a b . 1))", "(a b . 1)"),
+        ] {
+            match SexprParser::parse_str(input) {
+                Err(ParsingError::InvalidSyntax(_, found, _)) => assert_eq!(found, tok),
+                res => panic!("{} parsed as {:?}", input, res),
+            }
+        }
+        assert!(SexprParser::parse_str("#hash(. (a . 1))").is_err());
+    }
+
+    #[test]
+    fn test_parse_box_and_prefab() {
+        let parsed = SexprParser::parse_str("(#&1 #s(point 1 #&x) #s((point 1) 2) #s[empty])")
+            .unwrap()
+            .untag();
+        let sym = |s: &str| Sexpr::Atom(Atom::Symbol(s.to_string(), ()), ());
+        let int = |i| Sexpr::Atom(Atom::Integer(i, ()), ());
+        let boxed = |s| Sexpr::Atom(Atom::Boxed(Box::new(s), ()), ());
+        assert_eq!(
+            parsed,
+            Sexpr::List(
+                vec![
+                    boxed(int(1)),
+                    Sexpr::Prefab(Box::new(sym("point")), vec![int(1), boxed(sym("x"))], ()),
+                    Sexpr::Prefab(
+                        Box::new(Sexpr::List(vec![sym("point"), int(1)], ())),
+                        vec![int(2)],
+                        ()
+                    ),
+                    Sexpr::Prefab(Box::new(sym("empty")), vec![], ()),
+                ],
+                ()
+            )
+        );
+        for input in ["#s()", "#s(1 2)", "#s(point . 1)"] {
+            assert!(SexprParser::parse_str(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_hash_and_prefab_spans() {
+        let parsed = SexprParser::parse_str("(#hasheq((a . 1)) #s(p 1))").unwrap();
+        match parsed {
+            Sexpr::List(items, _) => {
+                assert_eq!(items[0].get_decorator().start_line_col(), (1, 2));
+                assert_eq!(items[0].get_decorator().end_line_col(), (1, 18));
+                assert_eq!(items[1].get_decorator().as_str(), "#s(p 1)");
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_display_hash_box_and_prefab() {
+        let input =
+            "(#hash((a . 1) (\"b\" . (2))) #hasheqv() #hashalw[(1 . 2)] #&#&x #s((p 1) 2 #&3))";
+        let parsed = SexprParser::parse_str(input).unwrap();
+        let displayed = parsed.to_string();
+        assert_eq!(
+            displayed,
+            "(#hash((a . 1) (\"b\" . (2))) #hasheqv() #hashalw((1 . 2)) #&#&x #s((p 1) 2 #&3))"
+        );
+        let reparsed = SexprParser::parse_str(&displayed).unwrap().untag();
+        assert_eq!(reparsed, parsed.untag());
+    }
+
     #[test]
     fn test_parse_nested() {
         let parsed = SexprParser::parse_str("(1 (2 3 4) (5 (2 3)))")
@@ -833,4 +2857,347 @@ mod sexpr_parser_tests {
             )
         );
     }
+
+    #[test]
+    fn test_parse_all_rejects_trailing_garbage() {
+        assert!(SexprParser::parse_all("(a) (b))").is_err());
+        assert!(SexprParser::parse_all("(a) (b) ; the end").is_ok());
+    }
+
+    #[test]
+    fn test_recovering_same_as_parse_all() {
+        let input =
+            "#;(skipped)\n(define (f x)\n  (+ x 1)) ; comment\n  (f \"λ\") #| block |#\n'(a . b)";
+        let (forms, errors) = SexprParser::parse_all_recovering(input);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(forms, SexprParser::parse_all(input).unwrap());
+    }
+
+    #[test]
+    fn test_recovering_stray_closer() {
+        let input = "(define x 1)\n(define y 2)\n(define z 3))\n(define w 4)\n";
+        let (forms, errors) = SexprParser::parse_all_recovering(input);
+        let names: Vec<_> = forms.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            names,
+            [
+                "(define x 1)",
+                "(define y 2)",
+                "(define z 3)",
+                "(define w 4)"
+            ]
+        );
+        assert_eq!(forms[3].get_decorator().start_line_col(), (4, 1));
+        match &errors[..] {
+            [ParsingError::UnbalancedBracket(e)] => {
+                assert!(e.opener.is_none());
+                assert_eq!(e.closer.as_ref().unwrap().start_line_col(), (3, 13));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_recovering_unclosed_form() {
+        let input = "(define (f x)\n  (+ x 1)\n(define y 2)\n(define (g x)\n  x)";
+        let (forms, errors) = SexprParser::parse_all_recovering(input);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        let names: Vec<_> = forms.iter().map(|f| f.to_string()).collect();
+        assert_eq!(names, ["(define y 2)", "(define (g x) x)"]);
+        assert_eq!(forms[1].get_decorator().start_line_col(), (4, 1));
+        assert_eq!(forms[1].get_decorator().end_line_col(), (5, 5));
+    }
+
+    #[test]
+    fn test_recovering_invalid_syntax_position() {
+        let input = "(a)\n  (b . ) (c)\n(#3(1 2 3 4))";
+        let (forms, errors) = SexprParser::parse_all_recovering(input);
+        assert_eq!(forms.len(), 2);
+        match &errors[..] {
+            [ParsingError::InvalidSyntax(dot, _, _), ParsingError::InvalidSyntax(vec, _, _)] => {
+                assert_eq!(dot.start_line_col(), (2, 6));
+                assert_eq!(vec.start_line_col(), (3, 2));
+                assert_eq!(vec.end_line_col(), (3, 13));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bracket_errors() {
+        match SexprParser::parse_all("(bla [bla)]") {
+            Err(e @ ParsingError::UnbalancedBracket(_)) => assert_eq!(
+                e.to_string(),
+                "Mismatched bracket: `[` at line 1, column 6 is closed by `)` at line 1, \
+                 column 10, expected `]`"
+            ),
+            res => panic!("{:?}", res),
+        }
+        match SexprParser::parse_str("(define (f x)\n  (+ x 1)\n\n(define y 2)") {
+            Err(e @ ParsingError::UnbalancedBracket(_)) => assert_eq!(
+                e.to_string(),
+                "Unclosed bracket: `(` at line 1, column 1 is never closed, expected `)`\n\
+                 The closing bracket is probably missing at line 2, column 10"
+            ),
+            res => panic!("{:?}", res),
+        }
+        // balanced brackets still get the error of the grammar
+        assert!(matches!(
+            SexprParser::parse_all("(a)\n#<<EOF\nb"),
+            Err(ParsingError::UnexpectedToken(..))
+        ));
+    }
+
+    #[test]
+    fn test_recovering_bracket_errors() {
+        let input = "(define (f x)\n  (+ x 1)\n(define y [2)\n(define z 3)";
+        let (forms, errors) = SexprParser::parse_all_recovering(input);
+        assert_eq!(forms.len(), 1);
+        match &errors[..] {
+            [ParsingError::UnbalancedBracket(unclosed), ParsingError::UnbalancedBracket(wrong)] => {
+                assert_eq!(unclosed.opener.as_ref().unwrap().start_line_col(), (1, 1));
+                assert!(unclosed.closer.is_none());
+                assert_eq!(
+                    unclosed.missing_at.as_ref().unwrap().start_line_col(),
+                    (2, 10)
+                );
+                assert_eq!(wrong.opener.as_ref().unwrap().start_line_col(), (3, 11));
+                assert_eq!(wrong.closer.as_ref().unwrap().start_line_col(), (3, 13));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unexpected_token_errors() {
+        for (input, start, expected, found) in [
+            (r#"(a "\q")"#, (1, 6), vec![Expected::Escape], "q"),
+            (
+                "(a)\n  #<<",
+                (2, 6),
+                vec![Expected::HereStringTerminator],
+                "",
+            ),
+            (
+                "(a)\n#<<EOF\nb",
+                (2, 2),
+                vec![Expected::OpeningBracket],
+                "<<EOF",
+            ),
+        ] {
+            match SexprParser::parse_all(input) {
+                Err(ParsingError::UnexpectedToken(pos, kinds, token)) => {
+                    assert_eq!(pos.start_line_col(), start, "{}", input);
+                    assert_eq!(kinds, expected, "{}", input);
+                    assert_eq!(token, found, "{}", input);
+                    assert_eq!(pos.as_str(), found, "{}", input);
+                }
+                res => panic!("{} parsed as {:?}", input, res),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unexpected_token_display() {
+        let err = SexprParser::parse_str(r#""a\q""#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unexpected `q` at line 1, column 4, expected a valid escape"
+        );
+        let err = SexprParser::parse_all("#<<").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unexpected end of input at line 1, column 4, \
+             expected the terminator of the here string"
+        );
+    }
+
+    #[test]
+    fn test_recovering_unexpected_token_position() {
+        let (forms, errors) = SexprParser::parse_all_recovering("(a)\n(b \"\\q\")\n(c)");
+        assert_eq!(forms.len(), 2);
+        match &errors[..] {
+            [ParsingError::UnexpectedToken(pos, kinds, found)] => {
+                assert_eq!(pos.start_line_col(), (2, 6));
+                assert_eq!(kinds, &[Expected::Escape]);
+                assert_eq!(found, "q");
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_line_col_matches_pest() {
+        let input = "(a\r\n λ) \n b\n\n  \"𝄞\r\n\" c\r\n";
+        let source = Source::new(input);
+        for (offset, _) in input.char_indices().chain([(input.len(), ' ')]) {
+            let pos = pest::Position::new(input, offset).unwrap();
+            assert_eq!(source.line_col(offset), pos.line_col(), "{}", offset);
+        }
+        // unlike pest, a lone `\r` ends a line, like in the files of classic Mac OS
+        let source = Source::new("(a)\r(b)");
+        assert_eq!(source.line_col(4), (2, 1));
+    }
+
+    #[test]
+    fn test_tokens_share_the_source() {
+        let input = "(define (f x)\n  (g \"λ\" x))";
+        let form = SexprParser::parse_str(input).unwrap();
+        let tok = form.get_decorator();
+        assert_eq!((tok.start(), tok.end()), (0, input.len()));
+        assert_eq!(tok.as_str(), input);
+        match &form {
+            Sexpr::List(l, _) => {
+                let body = l[2].get_decorator();
+                assert!(Arc::ptr_eq(body.source(), tok.source()));
+                assert_eq!((body.start(), body.end()), (16, input.len() - 1));
+                assert_eq!(body.as_str(), "(g \"λ\" x)");
+                assert_eq!(body.start_line_col(), (2, 3));
+                assert_eq!(body.end_line_col(), (2, 12));
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_recovering_offsets() {
+        let input = "(a)\n(b .)\n(c d)";
+        let (forms, errors) = SexprParser::parse_all_recovering(input);
+        assert_eq!(errors.len(), 1);
+        let d = match &forms[1] {
+            Sexpr::List(l, _) => l[1].get_decorator(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!((d.start(), d.as_str()), (13, "d"));
+        assert_eq!(d.start_line_col(), (3, 4));
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let n = 100_000;
+        for input in [
+            "(".repeat(n),
+            "'".repeat(n) + "a",
+            "(".repeat(n) + &")".repeat(n),
+            "#(".repeat(n) + &")".repeat(n),
+            "#;".repeat(n) + "a",
+            ",@".repeat(n),
+            "#hash((a . ".repeat(n),
+            "#|".repeat(n),
+            "a\n".to_string() + &"#| (".repeat(n),
+        ] {
+            match SexprParser::parse_all(&input) {
+                Err(ParsingError::TooDeep(_)) => {}
+                other => panic!("{:?}", other.map(|_| ())),
+            }
+            let (_, errors) = SexprParser::parse_all_recovering(&input);
+            assert!(matches!(errors[..], [ParsingError::TooDeep(_)]));
+            assert!(matches!(Cst::parse(&input), Err(ParsingError::TooDeep(_))));
+        }
+        let input = "'".repeat(n) + "a";
+        assert!(matches!(
+            SexprParser::parse_str(&input),
+            Err(ParsingError::TooDeep(_))
+        ));
+        assert!(!SexprParser::is_plain_symbol(&input));
+    }
+
+    #[test]
+    fn test_limits() {
+        let opts = ReaderOptions {
+            max_depth: 2,
+            max_forms: 5,
+            max_size: 20,
+            ..Default::default()
+        };
+        assert!(SexprParser::parse_all_with("(a b) 'c", &opts).is_ok());
+        for (input, at) in [("(a (b))", "b"), ("'(a)", "a"), ("#(#(1))", "1")] {
+            match SexprParser::parse_all_with(input, &opts) {
+                Err(ParsingError::TooDeep(tok)) => assert_eq!(tok.as_str(), at),
+                other => panic!("{:?}", other),
+            }
+        }
+        assert!(SexprParser::parse_all_with("a b c d e", &opts).is_ok());
+        for (input, at) in [("a b c d e f", "f"), ("(a b) (c d)", "d")] {
+            match SexprParser::parse_all_with(input, &opts) {
+                Err(ParsingError::TooManyForms(tok)) => assert_eq!(tok.as_str(), at),
+                other => panic!("{:?}", other),
+            }
+        }
+        let long = "a".repeat(21);
+        assert!(matches!(
+            SexprParser::parse_all_with(&long, &opts),
+            Err(ParsingError::TooLarge(21))
+        ));
+        assert!(matches!(
+            SexprParser::parse_str_with(&long, &opts),
+            Err(ParsingError::TooLarge(21))
+        ));
+
+        let shallow = ReaderOptions {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let (forms, errors) = SexprParser::parse_all_recovering_with("(a (b))\n(c)", &shallow);
+        assert_eq!(forms.len(), 1);
+        assert!(matches!(errors[..], [ParsingError::TooDeep(_)]));
+        let (forms, errors) = SexprParser::parse_all_recovering_with("a b c d e f g", &opts);
+        assert_eq!(forms.len(), 5);
+        assert!(matches!(errors[..], [ParsingError::TooManyForms(_)]));
+    }
+
+    /// Reads random inputs made of the pieces of the syntax, with every reader, checking that
+    /// none of them panics and that the fast one reads the same as the grammar.
+    #[test]
+    fn test_random_inputs() {
+        let pieces = [
+            "(", ")", "[", "]", "{", "}", " ", "\n", "\r", "\t", "a", "λ", "😀", "0", "1", "9",
+            "-", "+", ".", "/", "e", "i", "@", "#e", "#i", "#x", "#b", "#d", "inf.0", "nan.0", "'",
+            "`", ",", ",@", "#'", "#`", "#,", "#,@", "#&", "#;", "\"", "\\", "\\u", "\\x", "#\\",
+            "|", "#|", "|#", ";", "#!", "#!eof", "#(", "#2(", "#hash(", "#s(", "#rx", "#px", "#\"",
+            "#<<", "#t", "#true", "#f", "#:", "#lang", "\0", "\r\n", "\u{feff}", "#!/", "#! ",
+            "#<<EOF\n", "\nEOF", "#|x|#", "#hasheq(", "0x1F", "1_0", "\\u{a}", "((((", "'''",
+        ];
+        let options = [
+            ReaderOptions::default(),
+            ReaderOptions {
+                case_sensitive: false,
+                square_brackets: false,
+                decimal_as_exact: true,
+                ..Default::default()
+            },
+            ReaderOptions {
+                strict: true,
+                curly_brackets: false,
+                short_booleans: false,
+                ..Default::default()
+            },
+            // small limits, so that the forms too deep or too many are found in short inputs
+            ReaderOptions {
+                max_depth: 3,
+                max_forms: 8,
+                ..Default::default()
+            },
+        ];
+        for (i, input) in random_inputs(&pieces, 0x9e3779b97f4a7c15, 4000, 40).enumerate() {
+            let opts = &options[i % options.len()];
+            let parsed = SexprParser::parse_all_with(&input, opts);
+            let fast = FastParser::parse_all_with(&input, opts);
+            assert_eq!(
+                format!("{:?}", parsed),
+                format!("{:?}", fast),
+                "{:?}",
+                input
+            );
+            let cst = Cst::parse_with(&input, opts);
+            assert_eq!(
+                format!("{:?}", parsed.err()),
+                format!("{:?}", cst.err()),
+                "{:?}",
+                input
+            );
+            SexprParser::parse_all_recovering_with(&input, opts);
+            let _ = SexprParser::parse_str_with(&input, opts);
+        }
+    }
 }
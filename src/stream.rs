@@ -0,0 +1,410 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read},
+    sync::Arc,
+};
+
+use pest::{iterators::Pair, Parser};
+
+use crate::{
+    brackets,
+    errors::ParsingError,
+    sexpr::{self, Sexpr, Source, TokInfo},
+    sexpr_parser::{bom_len, has_lone_prefix, ReaderOptions, Rule, SexprParser},
+};
+
+impl SexprParser {
+    /// Reads the top-level forms of the given reader one at a time, see `Forms`.
+    pub fn forms<R: Read>(reader: R) -> Forms<R> {
+        Self::forms_with(reader, &ReaderOptions::default())
+    }
+
+    pub fn forms_with<R: Read>(reader: R, opts: &ReaderOptions) -> Forms<R> {
+        Forms {
+            reader: BufReader::new(reader),
+            opts: opts.clone(),
+            pending: String::new(),
+            offset: 0,
+            line: 1,
+            scanned: 0,
+            depth: 0,
+            retry_at: 0,
+            ready: VecDeque::new(),
+            error: None,
+            done: false,
+        }
+    }
+}
+
+/// An iterator over the top-level forms of a reader, which only reads as much of it as it needs
+/// to give the next form. The input is read line by line, and it's parsed whenever every bracket
+/// read so far is closed. The positions are the ones in the whole input, like with
+/// `SexprParser::parse_all`. The iterator stops after the first error, which is given once every
+/// bracket before it is closed, after the forms before it. The limits of the options apply to the
+/// lines parsed at once, rather than to the whole input.
+pub struct Forms<R> {
+    reader: BufReader<R>,
+    opts: ReaderOptions,
+    pending: String, // the lines read but not parsed yet
+    offset: usize,   // the byte offset of the pending lines in the whole input
+    line: usize,     // the line the pending lines start at
+    scanned: usize,  // how much of the pending lines was scanned for brackets
+    depth: isize,    // how many brackets of the pending lines are still open
+    // after the pending lines couldn't be parsed, how long they have to get before trying again,
+    // so that they aren't parsed again for every line
+    retry_at: usize,
+    ready: VecDeque<Sexpr<TokInfo>>,
+    error: Option<ParsingError>, // given after the ready forms
+    done: bool,
+}
+
+impl<R: Read> Forms<R> {
+    /// Reads the next line into the pending ones, giving back false at the end of the input.
+    fn read_line(&mut self) -> Result<bool, ParsingError> {
+        let read = self
+            .reader
+            .read_line(&mut self.pending)
+            .map_err(ParsingError::Io)?;
+        self.opts.check_size(self.pending.len())?;
+        let (found, resume) =
+            brackets::scan_brackets_resumable(&self.pending, self.scanned, self.pending.len());
+        for (_, bracket) in found {
+            self.depth += if matches!(bracket, '(' | '[' | '{') {
+                1
+            } else {
+                -1
+            };
+        }
+        self.scanned = resume;
+        Ok(read > 0)
+    }
+
+    /// Checks if the pending lines could be a whole number of forms, as every bracket, string
+    /// and comment is closed.
+    fn is_complete(&self) -> bool {
+        self.depth <= 0 && self.scanned == self.pending.len() && self.pending.len() >= self.retry_at
+    }
+
+    /// Parses the pending lines, unless they are cut in the middle of a form and there is more
+    /// input to read. When they can't be parsed, the forms before the error are still given.
+    fn parse_pending(&mut self, at_end: bool) -> Result<bool, ParsingError> {
+        let len = self.pending.len();
+        let source = Arc::new(Source::new_at(&self.pending, self.offset, self.line));
+        let read = match at_end {
+            true => Some(SexprParser::parse_range(&source, 0, len, &self.opts)),
+            false => (self.parse_closed(&source)).or_else(|| self.final_error(&source).map(Err)),
+        };
+        match read {
+            Some(Ok(forms)) => self.ready.extend(forms),
+            Some(Err(e)) => {
+                let (forms, _) = SexprParser::parse_range_recovering(&source, 0, len, &self.opts);
+                let before = |form: &Sexpr<TokInfo>| form.get_decorator().end() <= error_start(&e);
+                self.ready.extend(forms.into_iter().take_while(before));
+                return Err(e);
+            }
+            None => {
+                // parsing them again only once they're twice as long keeps it linear
+                self.retry_at = 2 * len;
+                return Ok(false);
+            }
+        }
+        self.offset += len;
+        self.line += sexpr::line_breaks(&self.pending).count();
+        self.pending.clear();
+        self.scanned = 0;
+        self.depth = 0;
+        self.retry_at = 0;
+        Ok(true)
+    }
+
+    /// Parses the pending lines with a `()` after them, which is only read as a form of its own
+    /// if the next lines can't change what the pending ones read as. Otherwise, something at the
+    /// end goes on in the next lines, like a `'`, a `#;`, a symbol that ends with an escaped line
+    /// break or a `#!` comment that ends with a backslash. The forms are given in `source`, which
+    /// has the pending lines without the `()`, and so is the error of a form that can't be read.
+    fn parse_closed(
+        &self,
+        source: &Arc<Source>,
+    ) -> Option<Result<Vec<Sexpr<TokInfo>>, ParsingError>> {
+        let text = format!("{}()", self.pending);
+        let closed = Arc::new(Source::new_at(&text, self.offset, self.line));
+        let opts = ReaderOptions {
+            max_size: self.opts.max_size.saturating_add(2),
+            ..self.opts.clone()
+        };
+        // the scanner can also be wrong about where a form ends, like when it takes the `;` of a
+        // symbol like `a;(` for a comment, so the errors of the grammar are left to `final_error`
+        let (pairs, base) = SexprParser::parse_program(&closed, 0, text.len(), &opts).ok()?;
+        // a pair that ends with the last line break, like a symbol with an escaped one at the end
+        let goes_on = |pair: Pair<Rule>| base + pair.as_span().end() == self.pending.len();
+        if has_lone_prefix(pairs.clone()) || pairs.clone().flatten().any(goes_on) {
+            return None;
+        }
+        let Ok(mut forms) = SexprParser::read_program(pairs, &closed, base, &opts) else {
+            // every form was read, so the one that can't be won't change with the next lines
+            return Some(SexprParser::parse_range(
+                source,
+                0,
+                self.pending.len(),
+                &self.opts,
+            ));
+        };
+        let end = self.offset + self.pending.len();
+        match forms.pop()? {
+            Sexpr::List(items, tok) if items.is_empty() && tok.start() == end => {}
+            _ => return None,
+        }
+        let forms = forms.into_iter().map(|form| {
+            form.map_tag(&mut |tok: TokInfo| {
+                TokInfo::new(
+                    source.clone(),
+                    tok.start() - self.offset,
+                    tok.end() - self.offset,
+                )
+            })
+        });
+        Some(Ok(forms.collect()))
+    }
+
+    /// Gives the error of the pending lines that the grammar can't read, even though their
+    /// brackets are closed, when the next lines can't make them readable. That's when a form is
+    /// nested too deep, or when a closing bracket is found where a top-level form should start,
+    /// like one that wasn't opened, as no form starts with one. Otherwise, the scanner could be
+    /// wrong about the forms being closed, or something before the error could go on in the
+    /// next lines.
+    fn final_error(&self, source: &Arc<Source>) -> Option<ParsingError> {
+        let len = self.pending.len();
+        let e = SexprParser::parse_range(source, 0, len, &self.opts).err()?;
+        match e {
+            ParsingError::TooDeep(_) => return Some(e),
+            ParsingError::UnexpectedToken(..) | ParsingError::UnbalancedBracket(_) => {}
+            _ => return None,
+        }
+        // the depth was checked when reading them, so the grammar can read them again
+        let mut at = bom_len(source, 0, len);
+        loop {
+            let trivia = SexprParser::parse(Rule::leading_trivia, &self.pending[at..]).ok()?;
+            at += trivia.as_str().len();
+            match SexprParser::parse(Rule::sexpr, &self.pending[at..]) {
+                Ok(pairs) => at += pairs.as_str().len(),
+                Err(_) => break,
+            }
+        }
+        // the scanner has to agree, or the closer could be in what the grammar reads as a comment
+        // once a prefix before it gets its form from the next lines
+        let closers = brackets::scan_brackets(&self.pending, 0, len);
+        let closer = closers
+            .iter()
+            .any(|&(i, c)| i == at && matches!(c, ')' | ']' | '}'));
+        closer.then_some(e)
+    }
+}
+
+/// Finds the offset of the first token of the given error, or 0 when it has none.
+fn error_start(e: &ParsingError) -> usize {
+    match e {
+        ParsingError::InvalidSyntax(tok, ..)
+        | ParsingError::UnexpectedToken(tok, ..)
+        | ParsingError::TooDeep(tok)
+        | ParsingError::TooManyForms(tok) => tok.start(),
+        ParsingError::UnbalancedBracket(e) => (e.opener.iter().chain(&e.closer))
+            .map(TokInfo::start)
+            .min()
+            .unwrap_or(0),
+        _ => 0,
+    }
+}
+
+impl<R: Read> Iterator for Forms<R> {
+    type Item = Result<Sexpr<TokInfo>, ParsingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ready.is_empty() && !self.done {
+            let step = self.read_line().and_then(|more| {
+                if !more {
+                    self.done = true;
+                }
+                if !more || self.is_complete() {
+                    self.parse_pending(!more)?;
+                }
+                Ok(())
+            });
+            if let Err(e) = step {
+                self.done = true;
+                self.error = Some(e);
+            }
+        }
+        match self.ready.pop_front() {
+            Some(form) => Some(Ok(form)),
+            None => self.error.take().map(Err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use std::io::{self, Read};
+
+    use crate::{
+        errors::ParsingError,
+        sexpr_parser::{ReaderOptions, SexprParser},
+        test_util::random_inputs,
+    };
+
+    fn assert_same_as_parse_all(input: &str) {
+        let streamed: Vec<_> = SexprParser::forms(input.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            streamed,
+            SexprParser::parse_all(input).unwrap(),
+            "{:?}",
+            input
+        );
+    }
+
+    #[test]
+    fn test_same_as_parse_all() {
+        for input in [
+            "",
+            "(a)",
+            "(define (f x)\n  (+ x 1))\n(f 2) (f 3)\n",
+            "(a \"(\n\" b)\n#| (\n |# (c)\n; )\n(d)",
+            "'\n(quoted on the next line) #;\n(commented)\n(e)",
+            "(a) '\n; comment\n\n(quoted) `\n,@\n(b)",
+            "#<<EOF\n(\nEOF\n(f |a\n(| b\\\n c)",
+            "(λ (x)\r\n  'λ)\r\n(g)\r\n",
+            "\u{feff}(a) '\r\n; c\r\n(quoted)\r\n; done",
+            "(a) ; c\r'\n(quoted)\r(b) '\r; c\n(quoted)\n",
+            "#! shebang \\\n (continued\n(h) #!eof (\n",
+            ",@#! |#|\r\n\r\\,@",
+            "(a ')\n#;'#! b\\\n(c)",
+            "a #;b\\\n#!eof\n",
+            "a;((\n)\n)\n(b)",
+        ] {
+            assert_same_as_parse_all(input);
+        }
+    }
+
+    #[test]
+    fn test_positions() {
+        let forms: Vec<_> = SexprParser::forms("(a)\n\n(b\n  c) (d)\n  (λ e)".as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        let starts: Vec<_> = forms
+            .iter()
+            .map(|f| {
+                (
+                    f.get_decorator().start(),
+                    f.get_decorator().start_line_col(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            starts,
+            [(0, (1, 1)), (5, (3, 1)), (13, (4, 6)), (19, (5, 3))]
+        );
+        assert_eq!(forms[3].get_decorator().as_str(), "(λ e)");
+    }
+
+    /// A reader that fails once everything before the failure was read.
+    struct Failing<'a>(&'a [u8]);
+
+    impl Read for Failing<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::Error::other("broken pipe"));
+            }
+            let len = buf.len().min(self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_lazy() {
+        // the forms before the failure are given before it's even read
+        let mut forms = SexprParser::forms(Failing(b"(a)\n(b\n"));
+        assert!(forms.next().unwrap().is_ok());
+        assert!(matches!(forms.next(), Some(Err(ParsingError::Io(_)))));
+        assert!(forms.next().is_none());
+    }
+
+    #[test]
+    fn test_stops_at_the_first_error() {
+        let mut forms = SexprParser::forms("(a)\n(b \"\\q\")\n(c)\n".as_bytes());
+        assert!(forms.next().unwrap().is_ok());
+        match forms.next() {
+            Some(Err(ParsingError::UnexpectedToken(tok, _, found))) => {
+                assert_eq!(tok.start_line_col(), (2, 6));
+                assert_eq!(found, "q");
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(forms.next().is_none());
+    }
+
+    #[test]
+    fn test_error_once_closed() {
+        // the error is given as soon as the brackets before it are closed, after the forms
+        let mut forms = SexprParser::forms(Failing(b"(a))\n(b)\n"));
+        assert_eq!(forms.next().unwrap().unwrap().to_string(), "(a)");
+        assert!(matches!(
+            forms.next(),
+            Some(Err(ParsingError::UnbalancedBracket(_)))
+        ));
+        assert!(forms.next().is_none());
+        let mut forms = SexprParser::forms(Failing(b"(a) (b . c d) (e)\n(f)\n"));
+        assert!(forms.next().unwrap().is_ok());
+        assert!(matches!(
+            forms.next(),
+            Some(Err(ParsingError::InvalidSyntax(..)))
+        ));
+        assert!(forms.next().is_none());
+    }
+
+    #[test]
+    fn test_unclosed_at_the_end() {
+        let mut forms = SexprParser::forms("(a)\n(b\n  (c)\n".as_bytes());
+        assert!(forms.next().unwrap().is_ok());
+        assert!(matches!(
+            forms.next(),
+            Some(Err(ParsingError::UnbalancedBracket(_)))
+        ));
+    }
+
+    #[test]
+    fn test_limits_apply_to_each_read() {
+        let opts = ReaderOptions {
+            max_size: 8,
+            ..Default::default()
+        };
+        let input = "(a)\n(b)\n(c)\n".repeat(10);
+        let forms = SexprParser::forms_with(input.as_bytes(), &opts);
+        assert_eq!(forms.filter(|form| form.is_ok()).count(), 30);
+        let mut forms = SexprParser::forms_with("(a\n b c d)\n".as_bytes(), &opts);
+        assert!(matches!(
+            forms.next(),
+            Some(Err(ParsingError::TooLarge(11)))
+        ));
+        assert!(forms.next().is_none());
+    }
+
+    #[test]
+    fn test_random_inputs() {
+        assert_same_as_parse_all("#;|\n|");
+        let pieces = [
+            "(", ")", "[", "]", " ", "\n", "\r", "\r\n", "a", "1", "1.5", "'", "`", ",", ",@",
+            "#;", "#&", "\"", "\\", "|", "#|", "|#", ";", "#! ", "#!eof", "#\\", "#(", "#hash(",
+            "#<<", "A", "λ", "\u{feff}",
+        ];
+        for input in random_inputs(&pieces, 0x2545f4914f6cdd1d, 5000, 30) {
+            let streamed: Result<Vec<_>, _> = SexprParser::forms(input.as_bytes()).collect();
+            match SexprParser::parse_all(&input) {
+                Ok(forms) => assert_eq!(streamed.ok(), Some(forms), "{:?}", input),
+                Err(_) => assert!(streamed.is_err(), "{:?}", input),
+            }
+        }
+    }
+}
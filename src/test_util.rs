@@ -0,0 +1,31 @@
+//! Helpers shared by the tests of the readers.
+
+/// A small deterministic generator for the random inputs.
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    /// A number below `n`.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    /// Fewer than `max_len` of the pieces, picked at random.
+    pub(crate) fn input(&mut self, pieces: &[&str], max_len: usize) -> String {
+        let len = self.below(max_len);
+        (0..len).map(|_| pieces[self.below(pieces.len())]).collect()
+    }
+}
+
+/// `count` random inputs made of the pieces, the same ones for the same seed.
+pub(crate) fn random_inputs<'a>(
+    pieces: &'a [&'a str],
+    seed: u64,
+    count: usize,
+    max_len: usize,
+) -> impl Iterator<Item = String> + 'a {
+    let mut rng = Rng(seed);
+    (0..count).map(move |_| rng.input(pieces, max_len))
+}
@@ -0,0 +1,510 @@
+use std::ops::Range;
+
+use crate::errors::ParsingError;
+
+/// How every file saved by DrRacket with non-text elements starts, followed by the version.
+const HEADER: &[u8] = b"#reader(lib\"read.ss\"\"wxme\")WXME";
+
+/// A program decoded from a WXME file, where the snips that aren't text were replaced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    pub text: String,
+    /// The snips that aren't plain text, in order.
+    pub snips: Vec<Snip>,
+}
+
+/// A snip that isn't plain text, and what it was replaced with in the decoded text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snip {
+    pub kind: SnipKind,
+    /// The name of the snip class, like `wximage`.
+    pub class: String,
+    /// The byte range of the replacement in the decoded text.
+    pub range: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnipKind {
+    /// An image, replaced with the symbol `|<image>|`.
+    Image,
+    /// A comment box, replaced with a block comment of its text.
+    Comment,
+    /// Any other snip, like an XML box, replaced with the symbol `|<snip>|`.
+    Other,
+}
+
+/// Checks if the input is a WXME file, which has to be decoded before it can be read.
+pub fn is_wxme(input: &[u8]) -> bool {
+    input.starts_with(HEADER)
+}
+
+/// Decodes the program of a WXME file. Only the textual format of version 8, which DrRacket has
+/// saved since version 4, is supported.
+///
+/// The decoding is best-effort: the text snips are found by their shape, a class index of text,
+/// a style, flags and the text, and whatever comes between two of them is taken as the other
+/// snips. The text of a comment box is the text snips up to the end of its editor.
+pub fn decode(input: &[u8]) -> Result<Decoded, ParsingError> {
+    let err = |msg: &str| ParsingError::InvalidWxme(msg.to_string());
+    let rest = input
+        .strip_prefix(HEADER)
+        .ok_or_else(|| err("The file doesn't start with the WXME header"))?;
+    if !rest.starts_with(b"0108") {
+        return Err(err("Only version 8 of the WXME format is supported"));
+    }
+    let body = rest
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(&[][..], |i| &rest[i..]);
+    let tokens = tokenize(body)?;
+
+    let mut reader = Tokens {
+        tokens: &tokens,
+        pos: 0,
+    };
+    let mut classes = Vec::new();
+    for _ in 0..reader.count("snip classes")? {
+        classes.push(reader.name("snip class name")?);
+        reader.int("snip class version")?;
+        reader.int("snip class required flag")?;
+    }
+    for _ in 0..reader.count("data classes")? {
+        reader.name("data class name")?;
+    }
+    let is_text = |index: i64| {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| classes.get(i))
+            .is_some_and(|class| class == "wxtext" || class == "wxtab")
+    };
+
+    let mut decoder = Decoder {
+        classes: &classes,
+        decoded: Decoded {
+            text: String::new(),
+            snips: Vec::new(),
+        },
+        comment: None,
+        started: false,
+    };
+    let mut gap_start = reader.pos;
+    let mut i = reader.pos;
+    while i + 3 < tokens.len() {
+        match &tokens[i..i + 4] {
+            [Token::Int(class), Token::Int(_), Token::Int(_), Token::Str(text)]
+                if is_text(*class) && !text.ends_with(b"\0") =>
+            {
+                decoder.gap(&tokens[gap_start..i]);
+                decoder.text(&String::from_utf8_lossy(text));
+                i += 4;
+                gap_start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    if !decoder.started {
+        return Err(err("The file has no text"));
+    }
+    decoder.gap(&tokens[gap_start..]);
+    decoder.close_comment();
+    Ok(decoder.decoded)
+}
+
+/// A value of the encoded stream.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Float,
+    /// A byte string, along with the length before it.
+    Str(Vec<u8>),
+}
+
+/// Splits the stream into numbers and byte strings, skipping the whitespace and the comments.
+/// A long byte string is split into parts between parentheses, which are joined back.
+fn tokenize(body: &[u8]) -> Result<Vec<Token>, ParsingError> {
+    let err = |msg: String| ParsingError::InvalidWxme(msg);
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut group: Option<Vec<u8>> = None;
+    while i < body.len() {
+        let rest = &body[i..];
+        if rest[0].is_ascii_whitespace() {
+            i += 1;
+        } else if rest.starts_with(b"#|") {
+            let end = rest
+                .windows(2)
+                .position(|w| w == b"|#")
+                .ok_or_else(|| err("A comment is never closed".to_string()))?;
+            i += end + 2;
+        } else if rest.starts_with(b"#\"") {
+            let (bytes, len) = read_byte_string(&rest[2..])?;
+            match &mut group {
+                Some(parts) => parts.extend(bytes),
+                None => push_string(&mut tokens, bytes),
+            }
+            i += 2 + len;
+        } else if rest[0] == b'(' && group.is_none() {
+            group = Some(Vec::new());
+            i += 1;
+        } else if rest[0] == b')' && group.is_some() {
+            push_string(&mut tokens, group.take().unwrap());
+            i += 1;
+        } else if rest[0] == b'-' || rest[0].is_ascii_digit() {
+            let len = rest[1..]
+                .iter()
+                .position(|b| !(b.is_ascii_digit() || *b == b'.'))
+                .map_or(rest.len(), |l| l + 1);
+            let number = std::str::from_utf8(&rest[..len]).unwrap();
+            tokens.push(match number.parse() {
+                Ok(n) => Token::Int(n),
+                Err(_) if number.parse::<f64>().is_ok() => Token::Float,
+                Err(_) => return Err(err(format!("Invalid number `{}`", number))),
+            });
+            i += len;
+        } else {
+            return Err(err(format!("Unexpected `{}`", rest[0].escape_ascii())));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Pushes a byte string, taking the length before it, which only says how long the string is.
+fn push_string(tokens: &mut Vec<Token>, bytes: Vec<u8>) {
+    if tokens.last() == Some(&Token::Int(bytes.len() as i64)) {
+        tokens.pop();
+    }
+    tokens.push(Token::Str(bytes));
+}
+
+/// Reads the rest of a byte string after its `#"`, giving back the bytes and how much of the
+/// input was read.
+fn read_byte_string(input: &[u8]) -> Result<(Vec<u8>, usize), ParsingError> {
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    loop {
+        match input.get(i) {
+            None => {
+                let msg = "A byte string is never closed".to_string();
+                return Err(ParsingError::InvalidWxme(msg));
+            }
+            Some(b'"') => return Ok((bytes, i + 1)),
+            Some(b'\\') => {
+                let c = input.get(i + 1).copied().unwrap_or(b'\\');
+                i += 2;
+                let digits = |radix: u32, max: usize, from: usize| {
+                    input[from..]
+                        .iter()
+                        .take(max)
+                        .take_while(|b| (**b as char).is_digit(radix))
+                        .count()
+                };
+                match c {
+                    b'0'..=b'7' => {
+                        let len = 1 + digits(8, 2, i);
+                        let octal = std::str::from_utf8(&input[i - 1..i - 1 + len]).unwrap();
+                        let byte = u8::try_from(u32::from_str_radix(octal, 8).unwrap());
+                        bytes.push(byte.map_err(|_| {
+                            let msg =
+                                format!("The escape `\\{}` is out of the range of a byte", octal);
+                            ParsingError::InvalidWxme(msg)
+                        })?);
+                        i += len - 1;
+                    }
+                    b'x' if digits(16, 2, i) > 0 => {
+                        let len = digits(16, 2, i);
+                        let hex = std::str::from_utf8(&input[i..i + len]).unwrap();
+                        bytes.push(u8::from_str_radix(hex, 16).unwrap());
+                        i += len;
+                    }
+                    b'a' => bytes.push(7),
+                    b'b' => bytes.push(8),
+                    b't' => bytes.push(b'\t'),
+                    b'n' => bytes.push(b'\n'),
+                    b'v' => bytes.push(11),
+                    b'f' => bytes.push(12),
+                    b'r' => bytes.push(b'\r'),
+                    b'e' => bytes.push(27),
+                    b'\n' => {}
+                    c => bytes.push(c),
+                }
+            }
+            Some(&b) => {
+                bytes.push(b);
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Reads the class lists at the start of the stream.
+struct Tokens<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Tokens<'_> {
+    fn next(&mut self, what: &str) -> Result<&Token, ParsingError> {
+        let token = self.tokens.get(self.pos).ok_or_else(|| {
+            ParsingError::InvalidWxme(format!("The file ends before the {}", what))
+        })?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn int(&mut self, what: &str) -> Result<i64, ParsingError> {
+        match self.next(what)? {
+            Token::Int(n) => Ok(*n),
+            _ => Err(ParsingError::InvalidWxme(format!("Invalid {}", what))),
+        }
+    }
+
+    fn count(&mut self, what: &str) -> Result<usize, ParsingError> {
+        let n = self.int(&format!("number of {}", what))?;
+        usize::try_from(n).map_err(|_| ParsingError::InvalidWxme(format!("Invalid {}", what)))
+    }
+
+    /// Reads a name, which is a byte string ending with a NUL.
+    fn name(&mut self, what: &str) -> Result<String, ParsingError> {
+        match self.next(what)? {
+            Token::Str(bytes) => {
+                let name = bytes.strip_suffix(b"\0").unwrap_or(bytes);
+                Ok(String::from_utf8_lossy(name).into_owned())
+            }
+            _ => Err(ParsingError::InvalidWxme(format!("Invalid {}", what))),
+        }
+    }
+}
+
+/// Puts the decoded text together, from the text snips and what comes between them.
+struct Decoder<'a> {
+    classes: &'a [String],
+    decoded: Decoded,
+    comment: Option<usize>, // where the comment box being decoded starts, if there is one
+    started: bool,          // whether the first text snip was decoded
+}
+
+impl Decoder<'_> {
+    fn text(&mut self, text: &str) {
+        self.started = true;
+        if self.comment.is_some() {
+            // the comment can't be closed by its own text
+            self.decoded.text.push_str(&text.replace("|#", "| #"));
+        } else {
+            self.decoded.text.push_str(text);
+        }
+    }
+
+    /// Decodes what comes between two text snips. It's nothing but the end of the snip before
+    /// when the snips are right after each other. Otherwise it's the start of another snip, or
+    /// the end of the editor of a comment box, while what comes before the first text snip is
+    /// the style list.
+    fn gap(&mut self, gap: &[Token]) {
+        let gap = match gap {
+            [Token::Int(0), rest @ ..] => rest,
+            gap => gap,
+        };
+        if !self.started || gap.is_empty() {
+            return;
+        }
+        if self.comment.is_some() {
+            self.close_comment();
+            return;
+        }
+        let class = match gap[0] {
+            Token::Int(index) => usize::try_from(index)
+                .ok()
+                .and_then(|i| self.classes.get(i)),
+            _ => None,
+        };
+        let Some(class) = class.filter(|class| *class != "wxtext" && *class != "wxtab") else {
+            return;
+        };
+        let start = self.decoded.text.len();
+        let kind = if class.contains("comment-snip") {
+            self.decoded.text.push_str("#|");
+            self.comment = Some(self.decoded.snips.len());
+            SnipKind::Comment
+        } else if class.contains("image") {
+            // the placeholders can't have a `|#` in them, which would close a comment around them
+            self.decoded.text.push_str("|<image>|");
+            SnipKind::Image
+        } else {
+            self.decoded.text.push_str("|<snip>|");
+            SnipKind::Other
+        };
+        self.decoded.snips.push(Snip {
+            kind,
+            class: class.clone(),
+            range: start..self.decoded.text.len(),
+        });
+    }
+
+    fn close_comment(&mut self) {
+        if let Some(index) = self.comment.take() {
+            self.decoded.text.push_str("|#");
+            self.decoded.snips[index].range.end = self.decoded.text.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod wxme_tests {
+    use super::{decode, is_wxme, SnipKind};
+    use crate::{
+        comments::CommentKind,
+        errors::ParsingError,
+        prog::Program,
+        sexpr::{Atom, Sexpr},
+    };
+
+    /// Writes a byte string like DrRacket does, after its length.
+    fn bytes(s: &str) -> String {
+        let escaped: String = s
+            .chars()
+            .map(|c| match c {
+                '\0' => "\\0".to_string(),
+                '\n' => "\\n".to_string(),
+                '"' | '\\' => format!("\\{}", c),
+                c => c.to_string(),
+            })
+            .collect();
+        format!("{} #\"{}\"", s.len(), escaped)
+    }
+
+    /// Writes a text snip, followed by the end of its data.
+    fn text(s: &str) -> String {
+        let flags = if s == "\n" { 29 } else { 3 };
+        format!("0 24 {} {}\n0 ", flags, bytes(s))
+    }
+
+    /// Writes a WXME file, with the snip classes of a program with an image and a comment box,
+    /// and the style list that comes before the text.
+    fn file(snips: &[String]) -> String {
+        let classes = [
+            "wxtext",
+            "wxtab",
+            "wximage",
+            "(lib \"comment-snip.ss\" \"framework\")",
+            "((lib \"image-core.ss\" \"mrlib\") (lib \"image-core-wxme.rkt\" \"mrlib\"))",
+        ];
+        let mut file = String::from(
+            "#reader(lib\"read.ss\"\"wxme\")WXME0108 ## \n#|\n   This file uses the GRacket \
+             editor format.\n|#\n",
+        );
+        file += &format!(" {} ", classes.len());
+        for class in classes {
+            file += &format!("{} 1 0\n", bytes(&format!("{}\0", class)));
+        }
+        // a long byte string is split into parts
+        file.push_str("1 11\n(\n #\"wxtex\"\n #\"tdata\\0\"\n)\n");
+        file.push_str("          0 0 64 0 1 #\"\\0\"\n0 75 1 #\"\\0\"\n");
+        file.push_str("0 12 90 -1 90 -1 3 -1 0 1 0 1 0 0 0.0 0.0 0.0 0.0 0.0 0.0 0 0 0 255\n");
+        file.push_str("255 255 1 -1 0 9\n#\"Standard\\0\"\n0 75 12 #\"Courier New\\0\"\n");
+        file.push_str("0 -1 1 #\"\\0\"\n          ");
+        file += &text("#lang racket");
+        file += &text("\n");
+        for snip in snips {
+            file += snip;
+        }
+        file + "          0 0\n"
+    }
+
+    #[test]
+    fn test_text_only() {
+        let file = file(&[
+            text("(define (f x) (+ x 1))"),
+            text("\n"),
+            text("(f \"\\\"\")"),
+        ]);
+        assert!(is_wxme(file.as_bytes()));
+        let decoded = decode(file.as_bytes()).unwrap();
+        assert_eq!(
+            decoded.text,
+            "#lang racket\n(define (f x) (+ x 1))\n(f \"\\\"\")"
+        );
+        assert!(decoded.snips.is_empty());
+    }
+
+    #[test]
+    fn test_image_and_comment_box() {
+        let image = format!(
+            "4 24 1 {}\n0 0 {}\n0 ",
+            bytes("\0"),
+            bytes("(image data |#)")
+        );
+        let comment = [
+            "3 24 1 ".to_string(),
+            bytes("\0"),
+            "\n0 0 0 0 0 0 0 0 0 0 -1 -1 -1 -1 0 1 0 0 0 0\n          ".to_string(),
+            text("the |# purpose"),
+            text("\n"),
+            text("of f"),
+            "          0 0 0 0\n0 ".to_string(),
+        ];
+        let file = file(&[
+            text("(define img "),
+            image,
+            text(")"),
+            text("\n"),
+            comment.concat(),
+            text("\n"),
+            text("(f img)"),
+        ]);
+        let decoded = decode(file.as_bytes()).unwrap();
+        assert_eq!(
+            decoded.text,
+            "#lang racket\n(define img |<image>|)\n#|the | # purpose\nof f|#\n(f img)"
+        );
+        let kinds: Vec<_> = decoded.snips.iter().map(|s| s.kind).collect();
+        assert_eq!(kinds, [SnipKind::Image, SnipKind::Comment]);
+        assert!(decoded.snips[0].class.contains("image-core"));
+        assert_eq!(&decoded.text[decoded.snips[0].range.clone()], "|<image>|");
+        assert_eq!(
+            &decoded.text[decoded.snips[1].range.clone()],
+            "#|the | # purpose\nof f|#"
+        );
+
+        // the placeholder is read as a symbol, and the comment box as a comment
+        let prog = Program::read(file.as_bytes()).unwrap();
+        assert_eq!(prog.hashlang, "racket");
+        assert_eq!(prog.body.len(), 2);
+        match &prog.body[0] {
+            Sexpr::List(l, _) => match &l[2] {
+                Sexpr::Atom(Atom::Symbol(name, tok), _) => {
+                    assert_eq!(name, "<image>");
+                    assert_eq!(tok.start(), decoded.snips[0].range.start);
+                }
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+        let comments: Vec<_> = prog
+            .comments_before(&prog.body[1])
+            .iter()
+            .map(|c| c.kind)
+            .collect();
+        assert_eq!(comments, [CommentKind::Block]);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(!is_wxme(b"#lang racket\n(f x)"));
+        assert!(matches!(
+            decode(b"#lang racket\n(f x)"),
+            Err(ParsingError::InvalidWxme(_))
+        ));
+        for body in [
+            "WXME0107 ## \n 1 7 #\"wxtext\\0\" 3 1 0", // an older version
+            "WXME0108 ## \n 2 7 #\"wxtext\\0\" 3 1",   // the class list is cut short
+            "WXME0108 ## \n 1 7 #\"wxtext\\0\" 3 1 0 #\"x", // a byte string is never closed
+            "WXME0108 ## \n 1 7 #\"wxtext\\0\" 3 1 0 0 0", // there's no text
+            "WXME0108 ## \n 1 7 #\"wxtext\\777\" 3 1 0", // an escape past 255
+        ] {
+            let file = format!("#reader(lib\"read.ss\"\"wxme\"){}", body);
+            assert!(matches!(
+                decode(file.as_bytes()),
+                Err(ParsingError::InvalidWxme(_))
+            ));
+        }
+    }
+}